@@ -0,0 +1,56 @@
+use tetris_rs::{ReplayFile, ReplaySummary};
+
+// ----
+
+// usage: verify-replay FILE.rep...
+//
+// Re-simulates each replay without a window, so it builds without the frontend
+// feature. Exits with 1 if any of them didn't come out the same, 2 without any files.
+fn main() {
+    let mut checked = 0;
+    let mut failed = 0;
+    for path in std::env::args().skip(1) {
+        checked += 1;
+
+        let file = match ReplayFile::load(&path) {
+            Ok(file) => file,
+            Err(error) => {
+                println!("{}: FAILED\n  {}", path, error);
+                failed += 1;
+                continue;
+            },
+        };
+
+        let result = file.verify();
+        let verdict = if result == file.summary { "OK" } else { "DESYNC" };
+        println!(
+            "{}: {}\n  {} rotation, {} randomizer, {} pieces, seed {}, {} frames",
+            path, verdict, file.rotation_system, file.randomizer, file.piece_set, file.replay.seed(), file.replay.frames().len(),
+        );
+        println!("  saved:  {}", describe(&file.summary));
+        println!("  replay: {}", describe(&result));
+
+        if result != file.summary {
+            failed += 1;
+        }
+    }
+
+    if checked == 0 {
+        eprintln!("usage: verify-replay FILE.rep...");
+        std::process::exit(2);
+    }
+
+    std::process::exit(if failed > 0 { 1 } else { 0 });
+}
+
+fn describe(summary: &ReplaySummary) -> String {
+    format!(
+        "score {}, lines {}, level {}, time {:.3}, {}, board {:016x}",
+        summary.score,
+        summary.lines,
+        summary.level,
+        summary.time,
+        summary.top_out.map_or("still going", |top_out| top_out.name()),
+        summary.board_hash,
+    )
+}
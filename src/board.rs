@@ -0,0 +1,351 @@
+use crate::bytes::{Reader, Writer};
+use crate::hash::Fnv1a;
+use crate::tetromino::Tetromino;
+
+// ----
+
+pub const DEFAULT_WIDTH: usize = 10;
+pub const DEFAULT_HEIGHT: usize = 20; // visible rows
+pub const MAX_BOARD_WIDTH: usize = RowMask::BITS as usize; // one bit per column
+
+/// A row of the board, or of a piece pattern: bit `col` is set where there's a block.
+pub type RowMask = u64;
+
+// ----
+
+/// The field as one bitmask per row, for fast collision and line checks, plus a
+/// layer of piece types for drawing. Cells are indexed row by row from the top left.
+#[derive(Clone)]
+pub struct Board {
+    rows: Vec<RowMask>, // top to bottom
+    tetromino_types: Vec<u8>, // per cell, for determining draw color, only meaningful where occupied
+    full_row: RowMask,
+    width: usize,
+    height: usize, // visible rows
+    hidden_rows: usize, // rows at the top which pieces can occupy but aren't drawn, as many as are visible
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new(DEFAULT_WIDTH, DEFAULT_HEIGHT)
+    }
+}
+
+impl Board {
+    /// A `width` by `height` field, with as many hidden rows again above it for pieces to spawn in.
+    /// `width` can't be more than `MAX_BOARD_WIDTH`.
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!(width <= MAX_BOARD_WIDTH, "boards can be at most {} columns wide", MAX_BOARD_WIDTH);
+
+        let hidden_rows = height;
+        let full_row = match width {
+            MAX_BOARD_WIDTH => RowMask::MAX,
+            _ => (1 << width) - 1,
+        };
+
+        Self {
+            rows: vec![0; hidden_rows + height],
+            tetromino_types: vec![0; width * (hidden_rows + height)],
+            full_row,
+            width,
+            height,
+            hidden_rows,
+        }
+    }
+
+    /// A board with its bottom rows drawn out, '#' for a block and '.' for empty.
+    #[cfg(test)]
+    pub(crate) fn from_picture(width: usize, height: usize, picture: &[&str]) -> Self {
+        let mut board = Self::new(width, height);
+        let top = board.total_rows() - picture.len();
+        for (row, line) in picture.iter().enumerate() {
+            assert_eq!(line.len(), width, "row {} of the picture isn't {} wide", row, width);
+            board.rows[top + row] = line.chars().enumerate().filter(|&(_, cell)| cell == '#').fold(0, |mask, (col, _)| mask | 1 << col);
+        }
+
+        board
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Visible rows only.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn hidden_rows(&self) -> usize {
+        self.hidden_rows
+    }
+
+    /// Every row, hidden and visible.
+    pub fn total_rows(&self) -> usize {
+        self.hidden_rows + self.height
+    }
+
+    /// How many cells there are, hidden and visible. Indices go up to here.
+    pub fn cell_count(&self) -> usize {
+        self.width * self.total_rows()
+    }
+
+    /// (row, col) of a cell.
+    pub fn row_col(&self, index: usize) -> (usize, usize) {
+        (index / self.width, index % self.width)
+    }
+
+    /// Whether a cell is above the visible field.
+    pub fn is_hidden(&self, index: usize) -> bool {
+        index / self.width < self.hidden_rows
+    }
+
+    pub fn get_index(&self, row: i32, col: i32) -> Option<usize> {
+        if row >= 0 && col >= 0 && (row as usize) < self.total_rows() && (col as usize) < self.width {
+            return Some(self.width * row as usize + col as usize);
+        }
+
+        None
+    }
+
+    pub fn is_occupied(&self, index: usize) -> bool {
+        let (row, col) = self.row_col(index);
+        self.rows[row] & (1 << col) != 0
+    }
+
+    /// Whether a cell is occupied, counting everything off the board as solid.
+    pub fn is_blocked(&self, row: i32, col: i32) -> bool {
+        match self.get_index(row, col) {
+            Some(index) => self.is_occupied(index),
+            None => true,
+        }
+    }
+
+    /// Which piece a cell was filled by, `None` if it's empty.
+    pub fn tetromino_type(&self, index: usize) -> Option<u8> {
+        if self.is_occupied(index) {
+            return Some(self.tetromino_types[index]);
+        }
+
+        None
+    }
+
+    pub fn row_mask(&self, row: usize) -> RowMask {
+        self.rows[row]
+    }
+
+    /// A fingerprint of every block and its piece type, the same on every build.
+    pub fn hash(&self) -> u64 {
+        let mut hash = Fnv1a::new();
+        hash.write(&(self.width as u64).to_le_bytes());
+        for (row, &mask) in self.rows.iter().enumerate() {
+            hash.write(&mask.to_le_bytes());
+            for col in (0..self.width).filter(|&col| mask & (1 << col) != 0) {
+                hash.write(&[self.tetromino_types[row * self.width + col]]);
+            }
+        }
+
+        hash.finish()
+    }
+
+    /// Whether a pattern, given as row masks with its top left corner at (`row`, `col`),
+    /// overlaps a block or sticks out of the board anywhere.
+    pub fn collides(&self, masks: &[RowMask], row: i32, col: i32) -> bool {
+        for (row_offset, &mask) in masks.iter().enumerate() {
+            if mask == 0 {
+                continue;
+            }
+
+            let board_row = row + row_offset as i32;
+            if board_row < 0 || board_row as usize >= self.total_rows() {
+                return true;
+            }
+
+            let shifted = if col < 0 {
+                let shift = (-col) as u32;
+                if shift >= RowMask::BITS || mask & ((1 << shift) - 1) != 0 {
+                    return true; // past the left wall
+                }
+                mask >> shift
+            }
+            else {
+                let shift = col as u32;
+                if shift >= RowMask::BITS || (mask << shift) >> shift != mask {
+                    return true; // past the right wall, and the end of the mask
+                }
+                mask << shift
+            };
+
+            if shifted & !self.full_row != 0 || shifted & self.rows[board_row as usize] != 0 {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Whether nothing is left once `rows` are cleared.
+    pub fn is_empty_except(&self, rows: &[usize]) -> bool {
+        self.rows.iter().enumerate().all(|(y, &mask)| mask == 0 || rows.contains(&y))
+    }
+
+    pub fn add_tetromino(&mut self, tetromino: &Tetromino) {
+        for &index in tetromino.positions.iter() {
+            let (row, col) = self.row_col(index);
+            self.rows[row] |= 1 << col;
+            self.tetromino_types[index] = tetromino.tetromino_type;
+        }
+    }
+
+    /// Every completely filled row, top to bottom.
+    pub fn full_rows(&self) -> Vec<usize> {
+        (0..self.total_rows()).filter(|&y| self.rows[y] == self.full_row).collect()
+    }
+
+    /// Removes `rows` and drops everything above them into the gap, in one pass from the bottom up.
+    pub fn clear_rows(&mut self, rows: &[usize]) {
+        let mut target = self.total_rows();
+        for y in (0..self.total_rows()).rev() {
+            if rows.contains(&y) {
+                continue;
+            }
+
+            target -= 1;
+            if target != y {
+                self.copy_row(y, target);
+            }
+        }
+
+        // whatever is left at the top came from above the board, which is empty
+        for mask in self.rows[..target].iter_mut() {
+            *mask = 0;
+        }
+    }
+
+    // each row's mask, then the piece type of every block in it, left to right
+    pub(crate) fn write(&self, writer: &mut Writer) {
+        for (row, &mask) in self.rows.iter().enumerate() {
+            writer.u64(mask);
+            for col in (0..self.width).filter(|&col| mask & (1 << col) != 0) {
+                writer.u8(self.tetromino_types[row * self.width + col]);
+            }
+        }
+    }
+
+    /// Fills in a fresh board of the right size from what `write` wrote.
+    pub(crate) fn read(&mut self, reader: &mut Reader) -> Result<(), String> {
+        for row in 0..self.total_rows() {
+            let mask = reader.u64()?;
+            if mask & !self.full_row != 0 {
+                return Err(format!("row {} is wider than the board", row));
+            }
+
+            self.rows[row] = mask;
+            for col in (0..self.width).filter(|&col| mask & (1 << col) != 0) {
+                self.tetromino_types[row * self.width + col] = reader.u8()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn copy_row(&mut self, from: usize, to: usize) {
+        self.rows[to] = self.rows[from];
+        self.tetromino_types.copy_within(from * self.width..(from + 1) * self.width, to * self.width);
+    }
+}
+
+// ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLAT_I: [RowMask; 1] = [0b1111];
+
+    #[test]
+    fn indices_stop_at_the_walls() {
+        let board = Board::default();
+        assert_eq!(board.get_index(0, 0), Some(0));
+        assert_eq!(board.get_index(39, 9), Some(399));
+        assert_eq!(board.get_index(0, -1), None);
+        assert_eq!(board.get_index(0, 10), None);
+        assert_eq!(board.get_index(-1, 0), None);
+        assert_eq!(board.get_index(40, 0), None);
+    }
+
+    #[test]
+    fn collides_past_the_walls() {
+        let board = Board::new(10, 20);
+        let bottom = board.total_rows() as i32 - 1;
+
+        assert!(!board.collides(&FLAT_I, bottom, 0));
+        assert!(board.collides(&FLAT_I, bottom, -1));
+        assert!(!board.collides(&FLAT_I, bottom, 6));
+        assert!(board.collides(&FLAT_I, bottom, 7));
+        assert!(board.collides(&FLAT_I, bottom + 1, 0));
+        assert!(board.collides(&FLAT_I, -1, 0));
+
+        // empty rows of a pattern can hang off the board
+        assert!(!board.collides(&[0b1111, 0], bottom, 0));
+        assert!(!board.collides(&[0, 0b1111], -1, 0));
+    }
+
+    #[test]
+    fn collides_past_the_walls_of_the_widest_board() {
+        let board = Board::new(MAX_BOARD_WIDTH, 20);
+        let last_col = MAX_BOARD_WIDTH as i32 - 4;
+
+        assert!(!board.collides(&FLAT_I, 0, last_col));
+        assert!(board.collides(&FLAT_I, 0, last_col + 1));
+        assert!(board.collides(&FLAT_I, 0, 64));
+        assert!(board.collides(&FLAT_I, 0, -1));
+        assert!(board.collides(&FLAT_I, 0, -64));
+        assert!(!board.collides(&[RowMask::MAX], 0, 0));
+        assert!(board.collides(&[RowMask::MAX], 0, 1));
+    }
+
+    #[test]
+    fn collides_with_the_stack() {
+        let board = Board::from_picture(4, 4, &[
+            "....",
+            "##.#",
+        ]);
+        let bottom = board.total_rows() as i32 - 1;
+
+        assert!(!board.collides(&[0b1], bottom, 2));
+        assert!(board.collides(&[0b11], bottom, 1));
+        assert!(!board.collides(&FLAT_I, bottom - 1, 0));
+    }
+
+    fn row(board: &Board, row: i32) -> String {
+        (0..10).map(|col| if board.is_occupied(board.get_index(row, col).unwrap()) { '#' } else { '.' }).collect()
+    }
+
+    #[test]
+    fn clears_rows_apart_from_each_other() {
+        let mut board = Board::from_picture(10, 20, &[
+            "#.........",
+            "##########",
+            ".#........",
+            "##########",
+            "..#.......",
+        ]);
+        assert_eq!(board.full_rows(), vec![36, 38]);
+
+        board.clear_rows(&board.full_rows());
+        let rows: Vec<String> = (35..40).map(|y| row(&board, y)).collect();
+        assert_eq!(rows, vec!["..........", "..........", "#.........", ".#........", "..#......."]);
+        assert!(board.full_rows().is_empty());
+    }
+
+    #[test]
+    fn only_cleared_rows_count_towards_empty() {
+        let board = Board::from_picture(10, 20, &[
+            "##########",
+            "#########.",
+        ]);
+
+        assert!(!board.is_empty_except(&[38]));
+        assert!(board.is_empty_except(&[38, 39]));
+    }
+}
@@ -0,0 +1,252 @@
+// Little-endian reading and writing for the save and replay files. Strings are a
+// u32 byte length followed by UTF-8. A file is a 4 byte magic, a u16 format
+// version, the contents, then an FNV-1a checksum of everything before it.
+
+use crate::hash::Fnv1a;
+
+// ----
+
+/// Wraps `contents` up as a file, see the top of this module.
+pub(crate) fn seal(magic: &[u8; 4], version: u16, contents: impl FnOnce(&mut Writer)) -> Vec<u8> {
+    let mut writer = Writer(Vec::new());
+    writer.bytes(magic);
+    writer.u16(version);
+    contents(&mut writer);
+
+    let mut checksum = Fnv1a::new();
+    checksum.write(&writer.0);
+    writer.u64(checksum.finish());
+
+    writer.0
+}
+
+/// Checks a file's magic, version and checksum, and hands back a reader over its contents.
+pub(crate) fn unseal<'a>(bytes: &'a [u8], magic: &[u8; 4], version: u16, what: &str) -> Result<Reader<'a>, String> {
+    if bytes.len() < magic.len() || &bytes[..magic.len()] != magic {
+        return Err(format!("not a {} file", what));
+    }
+
+    let mut reader = Reader { bytes, position: magic.len() };
+    let file_version = reader.u16()?;
+    if file_version != version {
+        return Err(format!("{} format version {}, this build only reads version {}", what, file_version, version));
+    }
+
+    // checked before anything else is trusted
+    if bytes.len() < reader.position + 8 {
+        return Err("the file ends early".to_owned());
+    }
+    let (contents, stored_checksum) = bytes.split_at(bytes.len() - 8);
+    let mut checksum = Fnv1a::new();
+    checksum.write(contents);
+    if checksum.finish().to_le_bytes() != stored_checksum {
+        return Err("checksum mismatch, the file is corrupt".to_owned());
+    }
+
+    reader.bytes = contents;
+    Ok(reader)
+}
+
+// ----
+
+pub(crate) struct Writer(Vec<u8>);
+
+impl Writer {
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn i32(&mut self, value: i32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn f64(&mut self, value: f64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes(value.as_bytes());
+    }
+
+    /// A u32 length, then the bytes.
+    pub(crate) fn byte_list(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.bytes(value);
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Errors unless everything has been read.
+    pub(crate) fn finish(&self) -> Result<(), String> {
+        match self.bytes.len() - self.position {
+            0 => Ok(()),
+            left => Err(format!("{} unexpected bytes at the end", left)),
+        }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let bytes = self.take_slice(N)?;
+        let mut array = [0; N];
+        array.copy_from_slice(bytes);
+        Ok(array)
+    }
+
+    fn take_slice(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.position.checked_add(len).filter(|&end| end <= self.bytes.len()).ok_or("the file ends early")?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, String> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(format!("expected a bool, found {}", byte)),
+        }
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    pub(crate) fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    pub(crate) fn f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_le_bytes(self.take()?))
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, String> {
+        let bytes = self.byte_list()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| "a string isn't valid UTF-8".to_owned())
+    }
+
+    pub(crate) fn byte_list(&mut self) -> Result<&'a [u8], String> {
+        let len = self.u32()? as usize;
+        self.take_slice(len)
+    }
+}
+
+// ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAGIC: &[u8; 4] = b"TEST";
+
+    fn sealed() -> Vec<u8> {
+        seal(MAGIC, 3, |writer| {
+            writer.u8(7);
+            writer.bool(true);
+            writer.u16(0xBEEF);
+            writer.u32(123_456);
+            writer.i32(-5);
+            writer.u64(u64::MAX);
+            writer.f64(0.25);
+            writer.string("block");
+            writer.byte_list(&[1, 2, 3]);
+        })
+    }
+
+    #[test]
+    fn reads_back_what_was_written() {
+        let bytes = sealed();
+        let mut reader = unseal(&bytes, MAGIC, 3, "test").unwrap();
+
+        assert_eq!(reader.u8(), Ok(7));
+        assert_eq!(reader.bool(), Ok(true));
+        assert_eq!(reader.u16(), Ok(0xBEEF));
+        assert_eq!(reader.u32(), Ok(123_456));
+        assert_eq!(reader.i32(), Ok(-5));
+        assert_eq!(reader.u64(), Ok(u64::MAX));
+        assert_eq!(reader.f64(), Ok(0.25));
+        assert_eq!(reader.string().as_deref(), Ok("block"));
+        assert_eq!(reader.byte_list(), Ok(&[1, 2, 3][..]));
+        assert_eq!(reader.finish(), Ok(()));
+    }
+
+    #[test]
+    fn any_flipped_byte_is_caught() {
+        let bytes = sealed();
+        for index in 0..bytes.len() {
+            let mut corrupt = bytes.clone();
+            corrupt[index] ^= 0x10;
+            assert!(unseal(&corrupt, MAGIC, 3, "test").is_err(), "byte {} flipped unnoticed", index);
+        }
+    }
+
+    #[test]
+    fn checks_the_magic_and_version() {
+        let bytes = sealed();
+
+        assert_eq!(unseal(&bytes, b"NOPE", 3, "test").err().as_deref(), Some("not a test file"));
+        assert_eq!(unseal(&bytes, MAGIC, 4, "test").err().as_deref(), Some("test format version 3, this build only reads version 4"));
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let bytes = sealed();
+        for len in 0..bytes.len() {
+            assert!(unseal(&bytes[..len], MAGIC, 3, "test").is_err(), "cut to {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn reading_past_the_end_fails() {
+        let bytes = seal(MAGIC, 3, |writer| writer.u32(1000));
+        let mut reader = unseal(&bytes, MAGIC, 3, "test").unwrap();
+
+        // a length which runs past the end of the contents
+        assert_eq!(reader.byte_list().err().as_deref(), Some("the file ends early"));
+    }
+
+    #[test]
+    fn leftover_bytes_are_rejected() {
+        let bytes = seal(MAGIC, 3, |writer| writer.u16(1));
+        let mut reader = unseal(&bytes, MAGIC, 3, "test").unwrap();
+        reader.u8().unwrap();
+
+        assert_eq!(reader.finish().err().as_deref(), Some("1 unexpected bytes at the end"));
+    }
+}
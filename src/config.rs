@@ -0,0 +1,247 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::{DEFAULT_HEIGHT, DEFAULT_WIDTH, MAX_BOARD_WIDTH};
+use crate::handling::Handling;
+use crate::piece_set::PieceSet;
+use crate::randomizer::RandomizerKind;
+use crate::rotation::RotationSystemKind;
+
+// ----
+
+pub const MAX_NEXT_QUEUE_LENGTH: usize = 6;
+pub const MIN_BOARD_SIZE: usize = 4; // bigger pieces need more, see `PieceSet::min_board_size`
+
+// ----
+
+/// What gives a grounded piece more time before it locks.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum LockDelayPolicy {
+    /// Every move or rotation restarts the timer, forever.
+    Infinite,
+    /// Moves and rotations restart the timer, up to this many times per lowest row reached.
+    MoveReset(u32),
+    /// Only reaching a new lowest row restarts the timer.
+    StepReset,
+}
+
+impl Default for LockDelayPolicy {
+    fn default() -> Self {
+        LockDelayPolicy::MoveReset(15)
+    }
+}
+
+impl FromStr for LockDelayPolicy {
+    type Err = String;
+
+    // "infinite", "step", "move" or "move:N" for a limit other than 15
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_ascii_lowercase();
+
+        match s.as_str() {
+            "infinite" => Ok(LockDelayPolicy::Infinite),
+            "step" => Ok(LockDelayPolicy::StepReset),
+            "move" => Ok(LockDelayPolicy::default()),
+            _ => match s.strip_prefix("move:").map(|limit| limit.parse()) {
+                Some(Ok(limit)) => Ok(LockDelayPolicy::MoveReset(limit)),
+                _ => Err(format!("unknown lock delay policy '{}', expected one of infinite, move, move:N, step", s)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for LockDelayPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LockDelayPolicy::Infinite => write!(f, "infinite"),
+            LockDelayPolicy::MoveReset(limit) => write!(f, "move:{}", limit),
+            LockDelayPolicy::StepReset => write!(f, "step"),
+        }
+    }
+}
+
+// written the same way in config and replay files
+
+impl TryFrom<String> for LockDelayPolicy {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<LockDelayPolicy> for String {
+    fn from(policy: LockDelayPolicy) -> Self {
+        policy.to_string()
+    }
+}
+
+// ----
+
+/// Everything which decides how a `Game` plays. Anything missing from a file is left at its default.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub rotation_system: RotationSystemKind,
+    pub randomizer: RandomizerKind,
+    pub board_width: usize,
+    pub board_height: usize, // visible rows, the same again are hidden above them
+    pub next_queue_length: usize, // 0 to MAX_NEXT_QUEUE_LENGTH
+    pub lock_delay: f64, // seconds
+    pub lock_delay_policy: LockDelayPolicy,
+    pub line_clear_delay: f64, // seconds full rows stay on the board before collapsing, for animations
+    pub partial_lock_out: bool, // top out when any part of a piece locks above the visible field, not just all of it
+    pub start_level: u32,
+    // TOML wants tables after plain values
+    pub handling: Handling,
+    pub pieces: Option<PieceSet>, // replaces the rotation system's pieces when set
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rotation_system: RotationSystemKind::default(),
+            randomizer: RandomizerKind::default(),
+            board_width: DEFAULT_WIDTH,
+            board_height: DEFAULT_HEIGHT,
+            next_queue_length: 5,
+            lock_delay: 0.5,
+            lock_delay_policy: LockDelayPolicy::default(),
+            line_clear_delay: 0.0,
+            partial_lock_out: false,
+            start_level: 1,
+            handling: Handling::default(),
+            pieces: None,
+        }
+    }
+}
+
+impl Config {
+    /// The pieces to play with: `pieces` if there are any, otherwise the rotation system's.
+    pub fn piece_set(&self) -> PieceSet {
+        self.pieces.clone().unwrap_or_else(|| self.rotation_system.piece_set())
+    }
+
+    /// Puts anything out of range back to its default, with a message for each
+    /// problem saying what was wrong. `Game::new` would quietly clamp some of these.
+    pub fn sanitize(&mut self) -> Vec<String> {
+        let defaults = Config::default();
+        let mut problems = Vec::new();
+
+        // big enough for every piece to spawn
+        let pieces = self.piece_set();
+        let (min_width, min_height) = pieces.min_board_size();
+        let for_pieces = match pieces.name.as_str() {
+            "" => "for these pieces".to_owned(),
+            name => format!("for the {} pieces", name),
+        };
+
+        if !(min_width..=MAX_BOARD_WIDTH).contains(&self.board_width) {
+            let default = self.rotation_system.default_board_width().clamp(min_width, MAX_BOARD_WIDTH);
+            problems.push(problem("board_width", format!("between {} and {} {}", min_width, MAX_BOARD_WIDTH, for_pieces), self.board_width, default));
+            self.board_width = default;
+        }
+        if self.board_height < min_height {
+            let default = defaults.board_height.max(min_height);
+            problems.push(problem("board_height", format!("at least {} {}", min_height, for_pieces), self.board_height, default));
+            self.board_height = default;
+        }
+        if self.next_queue_length > MAX_NEXT_QUEUE_LENGTH {
+            problems.push(problem("next_queue_length", format!("at most {}", MAX_NEXT_QUEUE_LENGTH), self.next_queue_length, defaults.next_queue_length));
+            self.next_queue_length = defaults.next_queue_length;
+        }
+        if self.start_level == 0 {
+            problems.push(problem("start_level", "at least 1", self.start_level, defaults.start_level));
+            self.start_level = defaults.start_level;
+        }
+
+        let seconds = [
+            ("lock_delay", &mut self.lock_delay, defaults.lock_delay),
+            ("line_clear_delay", &mut self.line_clear_delay, defaults.line_clear_delay),
+            ("handling.das", &mut self.handling.das, defaults.handling.das),
+            ("handling.arr", &mut self.handling.arr, defaults.handling.arr),
+            ("handling.das_cut_delay", &mut self.handling.das_cut_delay, defaults.handling.das_cut_delay),
+        ];
+        for (name, value, default) in seconds {
+            if !value.is_finite() || *value < 0.0 {
+                problems.push(problem(name, "a number of seconds, 0 or more", *value, default));
+                *value = default;
+            }
+        }
+
+        // infinity is fine here, it means dropping straight to the floor
+        let soft_drop_factor = &mut self.handling.soft_drop_factor;
+        if soft_drop_factor.is_nan() || *soft_drop_factor < 1.0 {
+            problems.push(problem("handling.soft_drop_factor", "1 or more, or inf", *soft_drop_factor, defaults.handling.soft_drop_factor));
+            *soft_drop_factor = defaults.handling.soft_drop_factor;
+        }
+
+        problems
+    }
+}
+
+/// Says `name` should have been `expected` but was `found`, so `default` is used instead.
+pub fn problem(name: &str, expected: impl fmt::Display, found: impl fmt::Display, default: impl fmt::Display) -> String {
+    format!("{} should be {}, found {}, using {} instead", name, expected, found, default)
+}
+
+// ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_have_no_problems() {
+        assert!(Config::default().sanitize().is_empty());
+    }
+
+    #[test]
+    fn boards_too_narrow_for_the_pieces_are_reported() {
+        let mut config = Config {
+            rotation_system: RotationSystemKind::Pentomino,
+            board_width: 4,
+            ..Config::default()
+        };
+
+        let problems = config.sanitize();
+        assert_eq!(problems, ["board_width should be between 5 and 64 for the pentomino pieces, found 4, using 12 instead"]);
+        assert_eq!(config.board_width, 12);
+    }
+
+    #[test]
+    fn custom_pieces_bigger_than_the_board_are_reported() {
+        let pieces = PieceSet::from_toml(concat!(
+            "name = 'long'\n",
+            "[[pieces]]\n",
+            "name = 'I7'\n",
+            "color = [0, 0, 0]\n",
+            "rotations = [['.......', '.......', '.......', '#######', '.......', '.......', '.......']]\n",
+        )).unwrap();
+        let mut config = Config {
+            board_width: 6,
+            pieces: Some(pieces),
+            ..Config::default()
+        };
+
+        assert_eq!(config.sanitize(), ["board_width should be between 7 and 64 for the long pieces, found 6, using 10 instead"]);
+    }
+
+    #[test]
+    fn out_of_range_values_go_back_to_their_defaults() {
+        let mut config = Config {
+            next_queue_length: 9,
+            start_level: 0,
+            lock_delay: -1.0,
+            ..Config::default()
+        };
+        config.handling.soft_drop_factor = 0.5;
+
+        assert_eq!(config.sanitize().len(), 4);
+        assert_eq!(config, Config::default());
+    }
+}
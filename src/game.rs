@@ -0,0 +1,1226 @@
+use std::collections::VecDeque;
+
+use crate::board::{Board, MAX_BOARD_WIDTH};
+use crate::config::{Config, LockDelayPolicy, MAX_NEXT_QUEUE_LENGTH};
+use crate::handling::AutoShift;
+use crate::input::Input;
+use crate::piece_set::PieceSet;
+use crate::randomizer::Randomizer;
+use crate::replay::Replay;
+use crate::rng::Rng;
+use crate::rotation::RotationSystem;
+use crate::scoring::{ClearScore, ClearType, Scoring};
+use crate::tetromino::Tetromino;
+use crate::tspin::detect_t_spin;
+
+mod save;
+
+pub use save::SAVE_FORMAT_VERSION;
+
+// ----
+
+/// Things which happened during an update, for frontends to react to.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    PieceLocked { tetromino_type: u8 },
+    PieceHeld { tetromino_type: u8 },
+    HardDrop { rows: u32 },
+    SonicDrop { rows: u32 },
+    Clear { clear_type: ClearType, score: ClearScore },
+    LevelUp { level: u32 },
+    LinesCleared { rows: Vec<usize> }, // top to bottom, still on the board until the line clear delay is over
+    GameOver { top_out: TopOut },
+}
+
+/// Why a game ended.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TopOut {
+    /// A new piece spawned overlapping the stack.
+    BlockOut,
+    /// A piece locked entirely above the visible field.
+    LockOut,
+    /// A piece locked partly above the visible field without clearing lines, see `Config::partial_lock_out`.
+    PartialLockOut,
+}
+
+impl TopOut {
+    pub fn name(self) -> &'static str {
+        match self {
+            TopOut::BlockOut => "BLOCK OUT",
+            TopOut::LockOut => "LOCK OUT",
+            TopOut::PartialLockOut => "PARTIAL LOCK OUT",
+        }
+    }
+
+    // for save and replay files: 0 for none, otherwise in declaration order starting at 1
+    pub(crate) fn to_byte(top_out: Option<TopOut>) -> u8 {
+        match top_out {
+            None => 0,
+            Some(TopOut::BlockOut) => 1,
+            Some(TopOut::LockOut) => 2,
+            Some(TopOut::PartialLockOut) => 3,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Result<Option<TopOut>, String> {
+        match byte {
+            0 => Ok(None),
+            1 => Ok(Some(TopOut::BlockOut)),
+            2 => Ok(Some(TopOut::LockOut)),
+            3 => Ok(Some(TopOut::PartialLockOut)),
+            _ => Err(format!("unknown top out {}", byte)),
+        }
+    }
+}
+
+// ----
+
+/// The rules of the game, free of any windowing or rendering. Feed it the
+/// held buttons and the time elapsed since the previous update.
+pub struct Game {
+    config: Config,
+    pieces: PieceSet,
+    rotation_system: Box<dyn RotationSystem>,
+    randomizer: Box<dyn Randomizer>,
+    seed: u64,
+    rng: Rng,
+    board: Board,
+    next_queue: VecDeque<Tetromino>,
+    current_shape: Tetromino,
+    hold_shape: Option<Tetromino>,
+    hold_used: bool,
+    last_rotation_kick: Option<usize>, // which kick the last rotation used, if the piece hasn't moved since
+    lock_timer: f64, // time spent on the ground
+    lock_resets: u32,
+    lowest_row: i32,
+    scoring: Scoring,
+    time: f64,
+    gravity_timer: f64,
+    shift_direction: i32, // -1 left, 1 right, whichever was pressed last
+    left_shift: AutoShift,
+    right_shift: AutoShift,
+    soft_drop_timer: f64,
+    das_cut_timer: f64,
+    clearing_rows: Vec<usize>, // full rows waiting out the line clear delay
+    line_clear_timer: f64,
+    top_out: Option<TopOut>, // set once the game is over, after which nothing moves
+    previous_input: Input,
+    events: Vec<Event>,
+    replay: Replay, // every update so far
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new(Config::default(), ::rand::random())
+    }
+}
+
+impl Game {
+    /// Two games built from the same config and seed, fed the same inputs, play out identically.
+    /// A board too small for the pieces is made just big enough.
+    pub fn new(mut config: Config, seed: u64) -> Self {
+        let pieces = config.piece_set();
+        let (min_width, min_height) = pieces.min_board_size();
+        config.next_queue_length = config.next_queue_length.min(MAX_NEXT_QUEUE_LENGTH);
+        config.board_width = config.board_width.clamp(min_width, MAX_BOARD_WIDTH);
+        config.board_height = config.board_height.max(min_height);
+
+        let rotation_system = config.rotation_system.build();
+        let scoring = Scoring::new(config.start_level);
+        let mut randomizer = config.randomizer.build(&pieces);
+        let mut rng = Rng::new(seed);
+        let board = Board::new(config.board_width, config.board_height);
+        let current_shape = spawn_shape(randomizer.next(&mut rng), &pieces, &board);
+        let next_queue = (0..config.next_queue_length)
+            .map(|_| spawn_shape(randomizer.next(&mut rng), &pieces, &board))
+            .collect();
+
+        let mut game = Self {
+            replay: Replay::new(config.clone(), seed),
+            config,
+            pieces,
+            rotation_system,
+            randomizer,
+            seed,
+            rng,
+            board,
+            next_queue,
+            current_shape: current_shape.clone(),
+            hold_shape: None,
+            hold_used: false,
+            last_rotation_kick: None,
+            lock_timer: 0.0,
+            lock_resets: 0,
+            lowest_row: current_shape.pattern_top_left_row,
+            scoring,
+            time: 0.0,
+            gravity_timer: 0.0,
+            shift_direction: 0,
+            left_shift: AutoShift::default(),
+            right_shift: AutoShift::default(),
+            soft_drop_timer: 0.0,
+            das_cut_timer: 0.0,
+            clearing_rows: Vec::new(),
+            line_clear_timer: 0.0,
+            top_out: None,
+            previous_input: Input::default(),
+            events: Vec::new(),
+        };
+
+        game.spawn(current_shape);
+        game
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The pieces being dealt, from `Config::pieces` or the rotation system.
+    pub fn pieces(&self) -> &PieceSet {
+        &self.pieces
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn current_shape(&self) -> &Tetromino {
+        &self.current_shape
+    }
+
+    /// Upcoming pieces, soonest first. As long as `Config::next_queue_length`.
+    pub fn next_queue(&self) -> &VecDeque<Tetromino> {
+        &self.next_queue
+    }
+
+    pub fn hold_shape(&self) -> Option<&Tetromino> {
+        self.hold_shape.as_ref()
+    }
+
+    /// Where the current piece would come to rest if dropped straight down.
+    pub fn ghost_positions(&self) -> Vec<usize> {
+        let offset = self.drop_distance() as usize * self.board.width();
+        self.current_shape.positions.iter().map(|&index| index + offset).collect()
+    }
+
+    pub fn scoring(&self) -> &Scoring {
+        &self.scoring
+    }
+
+    /// Seconds since the game started.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Full rows which are about to collapse. Empty unless the line clear delay is running,
+    /// during which there is no piece in play.
+    pub fn clearing_rows(&self) -> &[usize] {
+        &self.clearing_rows
+    }
+
+    /// Why the game ended, or `None` while it's still going.
+    pub fn top_out(&self) -> Option<TopOut> {
+        self.top_out
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.top_out.is_some()
+    }
+
+    /// Starts over with the same config. Everything but the seed is forgotten.
+    pub fn restart(&mut self, seed: u64) {
+        *self = Self::new(self.config.clone(), seed);
+    }
+
+    /// The game so far, for `Playback` to play out again.
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Whether the current piece has already been swapped into hold. Only once per piece.
+    pub fn hold_used(&self) -> bool {
+        self.hold_used
+    }
+
+    // ----
+
+    /// Advances the game by `dt` seconds and returns what happened along the way.
+    pub fn update(&mut self, input: Input, dt: f64) -> Vec<Event> {
+        if self.is_game_over() {
+            return Vec::new();
+        }
+
+        self.replay.record(input, dt);
+        self.time += dt;
+        self.das_cut_timer = (self.das_cut_timer - dt).max(0.0);
+
+        if !self.clearing_rows.is_empty() {
+            // inputs aren't taken in, so presses made during the delay count once the next piece is out
+            self.update_line_clear_delay(dt);
+            return std::mem::take(&mut self.events);
+        }
+
+        let gravity_rows = self.gravity_rows(dt);
+
+        if input.hold && !self.previous_input.hold {
+            self.hold_current_shape();
+            if self.is_game_over() {
+                return std::mem::take(&mut self.events);
+            }
+        }
+
+        if input.rotate_clockwise && !self.previous_input.rotate_clockwise && self.rotate_current_shape(true) {
+            self.after_rotation();
+        }
+        if input.rotate_counter_clockwise && !self.previous_input.rotate_counter_clockwise && self.rotate_current_shape(false) {
+            self.after_rotation();
+        }
+
+        // horizontal first, so sliding under an overhang works the same frame the piece drops
+        self.shift_current_shape(input, dt);
+
+        let soft_drop_rows = self.soft_drop_rows(input, dt);
+
+        if input.hard_drop && !self.previous_input.hard_drop {
+            let rows = self.drop_current_shape();
+            self.scoring.add_hard_drop(rows);
+            self.events.push(Event::HardDrop { rows });
+            self.lock_current_shape();
+        }
+        else {
+            if input.sonic_drop && !self.previous_input.sonic_drop {
+                let rows = self.drop_current_shape();
+                self.scoring.add_soft_drop(rows);
+                self.events.push(Event::SonicDrop { rows });
+            }
+            else {
+                self.move_current_shape_down(gravity_rows);
+
+                // only the rows the player pushed it down earn points
+                let rows = self.move_current_shape_down(soft_drop_rows);
+                self.scoring.add_soft_drop(rows);
+            }
+
+            self.update_lock_delay(dt);
+        }
+
+        self.previous_input = input;
+
+        std::mem::take(&mut self.events)
+    }
+
+    // ----
+
+    fn shift_current_shape(&mut self, input: Input, dt: f64) {
+        if input.left && !self.previous_input.left {
+            self.shift_direction = -1;
+            self.left_shift.release();
+        }
+        if input.right && !self.previous_input.right {
+            self.shift_direction = 1;
+            self.right_shift.release();
+        }
+
+        // letting go of the newer direction hands control back to the older one, as a fresh press
+        if self.shift_direction == -1 && !input.left {
+            self.shift_direction = if input.right { 1 } else { 0 };
+            self.right_shift.release();
+        }
+        if self.shift_direction == 1 && !input.right {
+            self.shift_direction = if input.left { -1 } else { 0 };
+            self.left_shift.release();
+        }
+
+        let handling = self.config.handling;
+        let cut = self.das_cut_timer > 0.0;
+        let steps = match self.shift_direction {
+            -1 => self.left_shift.update(dt, &handling, cut),
+            1 => self.right_shift.update(dt, &handling, cut),
+            _ => return,
+        };
+
+        let mut moved = false;
+        for _ in 0..steps {
+            if !self.move_current_shape(self.shift_direction, 0) {
+                break;
+            }
+            moved = true;
+        }
+
+        if moved {
+            self.reset_lock_delay_after_move();
+        }
+    }
+
+    fn gravity_rows(&mut self, dt: f64) -> u32 {
+        let interval = self.scoring.gravity();
+
+        self.gravity_timer += dt;
+        let rows = (self.gravity_timer / interval).floor();
+        self.gravity_timer -= rows * interval;
+
+        rows as u32
+    }
+
+    fn soft_drop_rows(&mut self, input: Input, dt: f64) -> u32 {
+        if !input.down {
+            return 0;
+        }
+
+        let soft_drop_factor = self.config.handling.soft_drop_factor.max(1.0);
+        if soft_drop_factor.is_infinite() {
+            return u32::MAX;
+        }
+
+        let interval = self.scoring.gravity() / soft_drop_factor;
+        if self.previous_input.down {
+            self.soft_drop_timer += dt;
+        }
+        else {
+            self.soft_drop_timer = interval; // first row right away
+        }
+
+        let rows = (self.soft_drop_timer / interval).floor();
+        self.soft_drop_timer -= rows * interval;
+
+        rows as u32
+    }
+
+    fn after_rotation(&mut self) {
+        self.reset_lock_delay_after_move();
+        self.das_cut_timer = self.config.handling.das_cut_delay;
+    }
+
+    fn rotate_current_shape(&mut self, clockwise: bool) -> bool {
+        let from = self.current_shape.rotation_pattern_index;
+        let count = self.pieces.rotation_count(self.current_shape.tetromino_type);
+        let rotation_index = if clockwise { (from + 1) % count } else { (from + count - 1) % count };
+        let pattern = self.pieces.pattern(self.current_shape.tetromino_type, rotation_index);
+        let masks = self.pieces.masks(self.current_shape.tetromino_type, rotation_index);
+
+        for (kick_index, (x_kick, y_kick)) in self.rotation_system.kicks(&self.pieces, &self.board, &self.current_shape, rotation_index, clockwise).into_iter().enumerate() {
+            let row = self.current_shape.pattern_top_left_row - y_kick; // kick tables count y upwards
+            let col = self.current_shape.pattern_top_left_col + x_kick;
+
+            if !self.board.collides(masks, row, col) {
+                self.current_shape.positions = Tetromino::transfer_shape_pattern_to_positions(&self.board, pattern, row, col)
+                    .expect("a pattern which doesn't collide is on the board");
+                self.current_shape.rotation_pattern_index = rotation_index;
+                self.current_shape.pattern_top_left_row = row;
+                self.current_shape.pattern_top_left_col = col;
+                self.last_rotation_kick = Some(kick_index);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    // whether the current piece would fit moved by the given offsets, checked a row mask at a time
+    fn fits(&self, x_offset: i32, y_offset: i32) -> bool {
+        let shape = &self.current_shape;
+        let masks = self.pieces.masks(shape.tetromino_type, shape.rotation_pattern_index);
+        !self.board.collides(masks, shape.pattern_top_left_row + y_offset, shape.pattern_top_left_col + x_offset)
+    }
+
+    // how far the current piece could fall
+    fn drop_distance(&self) -> u32 {
+        let mut rows = 0;
+        while self.fits(0, rows + 1) {
+            rows += 1;
+        }
+
+        rows as u32
+    }
+
+    fn move_current_shape(&mut self, x_offset: i32, y_offset: i32) -> bool {
+        if !self.fits(x_offset, y_offset) {
+            return false;
+        }
+
+        let offset = y_offset as isize * self.board.width() as isize + x_offset as isize;
+        for index in self.current_shape.positions.iter_mut() {
+            *index = (*index as isize + offset) as usize;
+        }
+        self.current_shape.pattern_top_left_row += y_offset;
+        self.current_shape.pattern_top_left_col += x_offset;
+        self.last_rotation_kick = None;
+        true
+    }
+
+    /// Moves the current piece down up to `rows` rows, returning how many it actually went.
+    fn move_current_shape_down(&mut self, rows: u32) -> u32 {
+        let mut moved = 0;
+        while moved < rows && self.move_current_shape(0, 1) {
+            moved += 1;
+        }
+
+        moved
+    }
+
+    /// Moves the current piece as far down as it goes, returning how many rows that was.
+    fn drop_current_shape(&mut self) -> u32 {
+        let rows = self.drop_distance();
+        if rows > 0 {
+            self.move_current_shape(0, rows as i32);
+        }
+
+        rows
+    }
+
+    fn is_grounded(&self) -> bool {
+        !self.fits(0, 1)
+    }
+
+    fn reset_lock_delay_after_move(&mut self) {
+        match self.config.lock_delay_policy {
+            LockDelayPolicy::Infinite => self.lock_timer = 0.0,
+            LockDelayPolicy::MoveReset(limit) => {
+                if self.lock_timer > 0.0 && self.lock_resets < limit {
+                    self.lock_timer = 0.0;
+                    self.lock_resets += 1;
+                }
+            },
+            LockDelayPolicy::StepReset => {},
+        }
+    }
+
+    fn update_lock_delay(&mut self, dt: f64) {
+        // every policy restarts the clock on reaching a new lowest row
+        if self.current_shape.pattern_top_left_row > self.lowest_row {
+            self.lowest_row = self.current_shape.pattern_top_left_row;
+            self.lock_timer = 0.0;
+            self.lock_resets = 0;
+        }
+
+        if !self.is_grounded() {
+            return;
+        }
+
+        self.lock_timer += dt;
+
+        let out_of_resets = match self.config.lock_delay_policy {
+            LockDelayPolicy::MoveReset(limit) => self.lock_resets >= limit,
+            _ => false,
+        };
+
+        if self.lock_timer >= self.config.lock_delay || out_of_resets {
+            self.lock_current_shape();
+        }
+    }
+
+    fn lock_current_shape(&mut self) {
+        let t_spin = detect_t_spin(&self.board, &self.pieces, &self.current_shape, self.last_rotation_kick);
+
+        self.board.add_tetromino(&self.current_shape);
+        self.events.push(Event::PieceLocked { tetromino_type: self.current_shape.tetromino_type });
+        self.hold_used = false;
+
+        let rows = self.board.full_rows();
+        let lines = rows.len() as u8;
+        if lines > 0 {
+            self.events.push(Event::LinesCleared { rows: rows.clone() });
+        }
+        else {
+            self.scoring.break_combo();
+        }
+
+        if let Some(clear_type) = ClearType::new(lines, t_spin) {
+            let level = self.scoring.level();
+            let perfect_clear = lines > 0 && self.board.is_empty_except(&rows);
+            let score = self.scoring.add_clear(clear_type, perfect_clear);
+            self.events.push(Event::Clear { clear_type, score });
+
+            if self.scoring.level() > level {
+                self.events.push(Event::LevelUp { level: self.scoring.level() });
+            }
+        }
+
+        let hidden_blocks = self.current_shape.positions.iter().filter(|&&index| self.board.is_hidden(index)).count();
+        if hidden_blocks == self.current_shape.positions.len() {
+            self.game_over(TopOut::LockOut);
+            return;
+        }
+        if hidden_blocks > 0 && lines == 0 && self.config.partial_lock_out {
+            self.game_over(TopOut::PartialLockOut);
+            return;
+        }
+
+        if lines > 0 && self.config.line_clear_delay > 0.0 {
+            self.clearing_rows = rows;
+            self.line_clear_timer = 0.0;
+            return;
+        }
+
+        self.board.clear_rows(&rows);
+        let next_shape = self.take_next_shape();
+        self.spawn(next_shape);
+    }
+
+    fn update_line_clear_delay(&mut self, dt: f64) {
+        self.line_clear_timer += dt;
+        if self.line_clear_timer < self.config.line_clear_delay {
+            return;
+        }
+
+        let rows = std::mem::take(&mut self.clearing_rows);
+        self.board.clear_rows(&rows);
+        let next_shape = self.take_next_shape();
+        self.spawn(next_shape);
+    }
+
+    fn hold_current_shape(&mut self) {
+        if self.hold_used {
+            return;
+        }
+
+        let tetromino_type = self.current_shape.tetromino_type;
+        let swapped_shape = match self.hold_shape.take() {
+            Some(hold_shape) => hold_shape,
+            None => self.take_next_shape(),
+        };
+
+        // back to spawn position and orientation
+        self.hold_shape = Some(spawn_shape(tetromino_type, &self.pieces, &self.board));
+        self.hold_used = true;
+        self.events.push(Event::PieceHeld { tetromino_type });
+        self.spawn(spawn_shape(swapped_shape.tetromino_type, &self.pieces, &self.board));
+    }
+
+    fn spawn(&mut self, tetromino: Tetromino) {
+        self.current_shape = tetromino;
+        self.last_rotation_kick = None;
+        self.lock_timer = 0.0;
+        self.lock_resets = 0;
+        self.das_cut_timer = self.config.handling.das_cut_delay;
+
+        if !self.fits(0, 0) {
+            self.game_over(TopOut::BlockOut);
+            return;
+        }
+
+        // pieces spawn just above the visible field and step straight into it when there's room
+        self.move_current_shape(0, 1);
+        self.lowest_row = self.current_shape.pattern_top_left_row;
+    }
+
+    // pushing before popping means an empty queue simply hands the new piece straight over
+    fn take_next_shape(&mut self) -> Tetromino {
+        let tetromino_type = self.randomizer.next(&mut self.rng);
+        self.next_queue.push_back(spawn_shape(tetromino_type, &self.pieces, &self.board));
+        self.next_queue.pop_front().unwrap()
+    }
+
+    fn game_over(&mut self, top_out: TopOut) {
+        self.top_out = Some(top_out);
+        self.events.push(Event::GameOver { top_out });
+    }
+}
+
+// ----
+
+// a piece at its spawn position, which `Game::new` made the board big enough for
+fn spawn_shape(tetromino_type: u8, pieces: &PieceSet, board: &Board) -> Tetromino {
+    Tetromino::new(tetromino_type, pieces, board).expect("the board is sized to fit every piece")
+}
+
+// ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::RowMask;
+    use crate::config::MIN_BOARD_SIZE;
+    use crate::handling::Handling;
+    use crate::rotation::RotationSystemKind;
+
+    const NOTHING: Input = Input {
+        left: false,
+        right: false,
+        down: false,
+        rotate_clockwise: false,
+        rotate_counter_clockwise: false,
+        hold: false,
+        hard_drop: false,
+        sonic_drop: false,
+    };
+    const DOWN: Input = Input { down: true, ..NOTHING };
+    const HOLD: Input = Input { hold: true, ..NOTHING };
+    const HARD_DROP: Input = Input { hard_drop: true, ..NOTHING };
+    const SONIC_DROP: Input = Input { sonic_drop: true, ..NOTHING };
+    const LEFT: Input = Input { left: true, ..NOTHING };
+    const RIGHT: Input = Input { right: true, ..NOTHING };
+    const CLOCKWISE: Input = Input { rotate_clockwise: true, ..NOTHING };
+    const COUNTER_CLOCKWISE: Input = Input { rotate_counter_clockwise: true, ..NOTHING };
+
+    const T_TETROMINO: u8 = 6; // in every built-in piece set
+
+    // a game on `board`, with nothing but `tetromino_type` pieces coming from the middle of the top
+    fn game_with(board: Board, tetromino_type: u8) -> Game {
+        let mut game = Game::new(Config::default(), 0);
+        game.board = board;
+        game.current_shape = spawn_shape(tetromino_type, &game.pieces, &game.board);
+        let next = spawn_shape(tetromino_type, &game.pieces, &game.board);
+        game.next_queue.iter_mut().for_each(|shape| *shape = next.clone());
+        game
+    }
+
+    // flat I pieces
+    fn game_with_i(board: Board) -> Game {
+        game_with(board, 0)
+    }
+
+    // holds down, a step at a time, until something comes of it
+    fn drop_until_event(game: &mut Game) -> Vec<Event> {
+        for _ in 0..100 {
+            let events = game.update(DOWN, 0.2);
+            if !events.is_empty() {
+                return events;
+            }
+        }
+
+        panic!("nothing happened");
+    }
+
+    fn is_occupied(game: &Game, row: i32, col: i32) -> bool {
+        game.board().is_occupied(game.board().get_index(row, col).unwrap())
+    }
+
+    // taps each button in turn, letting go in between, with no time passing
+    fn tap(game: &mut Game, inputs: &[Input]) -> Vec<Event> {
+        let mut events = Vec::new();
+        for &input in inputs {
+            events.extend(game.update(input, 0.0));
+            events.extend(game.update(NOTHING, 0.0));
+        }
+
+        events
+    }
+
+    #[test]
+    fn pieces_spawn_above_the_field_and_step_into_it() {
+        let game = Game::new(Config::default(), 0);
+
+        let lowest_row = game.current_shape().positions.iter().map(|&index| game.board().row_col(index).0).max();
+        assert_eq!(lowest_row, Some(game.board().hidden_rows()));
+    }
+
+    #[test]
+    fn pieces_spawn_centered_on_any_width() {
+        // I is 4 wide, odd gaps leave it a column left of center
+        for (width, left_col) in [(10, 3), (11, 3), (4, 0), (7, 1)] {
+            let game = game_with_i(Board::new(width, 20));
+            let cols: Vec<usize> = game.current_shape().positions.iter().map(|&index| game.board().row_col(index).1).collect();
+            assert_eq!(cols, (left_col..left_col + 4).collect::<Vec<_>>(), "width {width}");
+        }
+    }
+
+    #[test]
+    fn boards_too_small_for_the_pieces_are_widened() {
+        let config = Config { board_width: 2, board_height: 1, ..Config::default() };
+
+        let mut game = Game::new(config, 0);
+        assert_eq!((game.board().width(), game.board().height()), (MIN_BOARD_SIZE, MIN_BOARD_SIZE));
+
+        // every piece still spawns until the stack tops out
+        tap(&mut game, &[HARD_DROP; 100]);
+        assert!(game.is_game_over());
+    }
+
+    #[test]
+    fn pentominoes_get_a_fifth_column() {
+        let config = Config {
+            rotation_system: RotationSystemKind::Pentomino,
+            board_width: 4,
+            board_height: 4,
+            ..Config::default()
+        };
+
+        let mut game = Game::new(config, 1);
+        assert_eq!((game.board().width(), game.board().height()), (5, 4));
+
+        // deal plenty of pieces, I5 among them, until it tops out
+        tap(&mut game, &[HARD_DROP; 100]);
+        assert!(game.is_game_over());
+    }
+
+    #[test]
+    fn boards_are_widened_for_custom_pieces_too() {
+        let pieces = PieceSet::from_toml(concat!(
+            "[[pieces]]\n",
+            "name = 'I7'\n",
+            "color = [0, 0, 0]\n",
+            "rotations = [['.......', '.......', '.......', '#######', '.......', '.......', '.......']]\n",
+        )).unwrap();
+        let config = Config {
+            board_width: 6,
+            pieces: Some(pieces),
+            ..Config::default()
+        };
+
+        let game = Game::new(config, 0);
+        assert_eq!(game.board().width(), 7);
+        assert_eq!(game.current_shape().positions.len(), 7);
+    }
+
+    #[test]
+    fn six_lines_at_once_count() {
+        let pieces = PieceSet::from_toml(concat!(
+            "[[pieces]]\n",
+            "name = 'I6'\n",
+            "color = [0, 0, 0]\n",
+            "rotations = [['..#...', '..#...', '..#...', '..#...', '..#...', '..#...']]\n",
+        )).unwrap();
+        let config = Config {
+            board_width: 4,
+            pieces: Some(pieces),
+            ..Config::default()
+        };
+
+        // four of them side by side, spawning in the second column
+        let mut game = Game::new(config, 1);
+        let events = tap(&mut game, &[LEFT, HARD_DROP, HARD_DROP, RIGHT, HARD_DROP, RIGHT, RIGHT, HARD_DROP]);
+
+        assert!(events.contains(&Event::LinesCleared { rows: (game.board().total_rows() - 6..game.board().total_rows()).collect() }));
+        assert!(events.iter().any(|event| matches!(event, Event::Clear { clear_type: ClearType::Lines(6), .. })));
+        assert_eq!(game.scoring().lines(), 6);
+    }
+
+    #[test]
+    fn pieces_lock_on_the_floor() {
+        let mut game = game_with_i(Board::default());
+
+        assert_eq!(drop_until_event(&mut game), vec![Event::PieceLocked { tetromino_type: 0 }]);
+        assert!((3..7).all(|col| is_occupied(&game, 39, col)));
+    }
+
+    #[test]
+    fn the_ghost_rests_on_the_stack() {
+        let game = game_with_i(Board::from_picture(10, 20, &["#####....."]));
+
+        let row_38: Vec<usize> = (3..7).map(|col| game.board().get_index(38, col).unwrap()).collect();
+        assert_eq!(game.ghost_positions().to_vec(), row_38);
+    }
+
+    #[test]
+    fn hard_drops_lock_at_the_bottom() {
+        let mut game = game_with_i(Board::default());
+
+        assert_eq!(game.update(HARD_DROP, 0.0), vec![Event::HardDrop { rows: 20 }, Event::PieceLocked { tetromino_type: 0 }]);
+        assert!((3..7).all(|col| is_occupied(&game, 39, col)));
+    }
+
+    #[test]
+    fn sonic_drops_leave_the_piece_in_play() {
+        let mut game = game_with_i(Board::from_picture(10, 20, &["#####....."]));
+
+        assert_eq!(game.update(SONIC_DROP, 0.0), vec![Event::SonicDrop { rows: 19 }]);
+        assert_eq!(game.current_shape().positions, game.ghost_positions());
+        assert!((3..7).all(|col| !is_occupied(&game, 38, col)));
+    }
+
+    #[test]
+    fn full_rows_clear() {
+        let mut game = game_with_i(Board::from_picture(10, 20, &[
+            "###.......",
+            "###....###",
+        ]));
+
+        let events = drop_until_event(&mut game);
+        assert_eq!(events, vec![
+            Event::PieceLocked { tetromino_type: 0 },
+            Event::LinesCleared { rows: vec![39] },
+            Event::Clear {
+                clear_type: ClearType::Single,
+                score: ClearScore { points: 100, combo: 0, back_to_back: false, perfect_clear: false },
+            },
+        ]);
+
+        // the row above came down
+        assert!((0..10).all(|col| is_occupied(&game, 39, col) == (col < 3)));
+        assert!((0..10).all(|col| !is_occupied(&game, 38, col)));
+    }
+
+    #[test]
+    fn full_rows_wait_out_the_line_clear_delay() {
+        let mut game = game_with_i(Board::from_picture(10, 20, &[
+            "###.......",
+            "###....###",
+        ]));
+        game.config.line_clear_delay = 0.3;
+
+        drop_until_event(&mut game);
+        assert_eq!(game.clearing_rows(), &[39]);
+        assert!((0..10).all(|col| is_occupied(&game, 39, col)));
+
+        game.update(NOTHING, 0.2);
+        assert_eq!(game.clearing_rows(), &[39]);
+
+        game.update(NOTHING, 0.2);
+        assert!(game.clearing_rows().is_empty());
+        assert!((0..10).all(|col| is_occupied(&game, 39, col) == (col < 3)));
+    }
+
+    #[test]
+    fn blocked_spawns_are_a_block_out() {
+        // something left right where pieces spawn, just above the field
+        let mut picture = vec!["....#....."];
+        picture.extend([".........."; 20]);
+        let mut game = game_with_i(Board::from_picture(10, 20, &picture));
+        game.current_shape = Tetromino::at(0, &game.pieces, &game.board, 0, 37, 0);
+
+        let events = game.update(HARD_DROP, FRAME);
+        assert_eq!(events, vec![
+            Event::HardDrop { rows: 1 },
+            Event::PieceLocked { tetromino_type: 0 },
+            Event::GameOver { top_out: TopOut::BlockOut },
+        ]);
+        assert_eq!(game.top_out(), Some(TopOut::BlockOut));
+
+        // and everything stays put
+        let rows: Vec<RowMask> = (0..game.board().total_rows()).map(|row| game.board().row_mask(row)).collect();
+        assert!(game.update(DOWN, 1.0).is_empty());
+        assert!((0..game.board().total_rows()).all(|row| game.board().row_mask(row) == rows[row]));
+    }
+
+    #[test]
+    fn locking_entirely_in_the_hidden_rows_is_a_lock_out() {
+        // stacked to the top of the field, so the I can't leave the row above it
+        let mut game = game_with_i(Board::from_picture(10, 20, &[".#########"; 20]));
+
+        let events = drop_until_event(&mut game);
+        assert_eq!(events, vec![Event::PieceLocked { tetromino_type: 0 }, Event::GameOver { top_out: TopOut::LockOut }]);
+    }
+
+    #[test]
+    fn partial_lock_outs_only_count_when_turned_on() {
+        // a T across the last hidden row and the first visible one, which can't fall
+        let game = |partial_lock_out| {
+            let mut game = game_with(Board::from_picture(10, 20, &[".#########"; 19]), T_TETROMINO);
+            game.current_shape = Tetromino::at(T_TETROMINO, &game.pieces, &game.board, 0, 19, 3);
+            game.config.partial_lock_out = partial_lock_out;
+            game
+        };
+
+        assert!(drop_until_event(&mut game(true)).contains(&Event::GameOver { top_out: TopOut::PartialLockOut }));
+
+        // without it the T stays, and the next one has nowhere to spawn
+        assert!(drop_until_event(&mut game(false)).contains(&Event::GameOver { top_out: TopOut::BlockOut }));
+    }
+
+    #[test]
+    fn partial_lock_outs_which_clear_lines_play_on() {
+        let mut picture = vec!["###...####"];
+        picture.extend([".#########"; 19]);
+        let mut game = game_with(Board::from_picture(10, 20, &picture), T_TETROMINO);
+        game.current_shape = Tetromino::at(T_TETROMINO, &game.pieces, &game.board, 0, 19, 3);
+        game.config.partial_lock_out = true;
+
+        let events = drop_until_event(&mut game);
+        assert!(events.contains(&Event::LinesCleared { rows: vec![20] }));
+        assert!(!events.contains(&Event::GameOver { top_out: TopOut::PartialLockOut }));
+    }
+
+    #[test]
+    fn hold_swaps_once_per_piece() {
+        let mut game = game_with_i(Board::default());
+        game.current_shape = spawn_shape(T_TETROMINO, &game.pieces, &game.board);
+
+        // an empty slot takes the next piece
+        assert_eq!(game.update(HOLD, 0.0), vec![Event::PieceHeld { tetromino_type: 6 }]);
+        assert_eq!(game.current_shape().tetromino_type, 0);
+        assert_eq!(game.hold_shape().map(|shape| shape.tetromino_type), Some(6));
+
+        // and that's it until the piece locks
+        game.update(NOTHING, 0.0);
+        assert!(game.update(HOLD, 0.0).is_empty());
+        assert_eq!(game.current_shape().tetromino_type, 0);
+
+        drop_until_event(&mut game);
+        game.update(NOTHING, 0.0);
+        assert_eq!(game.update(HOLD, 0.0), vec![Event::PieceHeld { tetromino_type: 0 }]);
+        assert_eq!(game.current_shape().tetromino_type, 6);
+
+        // spawned afresh, and stepped down into the field like any new piece
+        assert_eq!(game.current_shape().pattern_top_left_row, spawn_shape(T_TETROMINO, &game.pieces, &game.board).pattern_top_left_row + 1);
+    }
+
+    #[test]
+    fn the_next_queue_deals_in_order() {
+        for length in [0, 3, 6] {
+            let mut game = Game::new(Config { next_queue_length: length, ..Config::default() }, 7);
+            let mut upcoming: Vec<u8> = game.next_queue().iter().map(|shape| shape.tetromino_type).collect();
+            assert_eq!(upcoming.len(), length);
+
+            for _ in 0..5 {
+                drop_until_event(&mut game);
+                if length > 0 {
+                    assert_eq!(game.current_shape().tetromino_type, upcoming.remove(0));
+                }
+                upcoming = game.next_queue().iter().map(|shape| shape.tetromino_type).collect();
+                assert_eq!(upcoming.len(), length);
+            }
+        }
+
+        let too_long = Game::new(Config { next_queue_length: 10, ..Config::default() }, 7);
+        assert_eq!(too_long.next_queue().len(), MAX_NEXT_QUEUE_LENGTH);
+    }
+
+    const FRAME: f64 = 1.0 / 60.0;
+
+    fn game_with_handling(handling: Handling) -> Game {
+        let mut game = game_with_i(Board::default());
+        game.config.handling = handling;
+        game
+    }
+
+    fn col(game: &Game) -> i32 {
+        game.current_shape().pattern_top_left_col
+    }
+
+    #[test]
+    fn soft_drop_moves_sdf_times_as_fast_as_gravity() {
+        let mut game = game_with_handling(Handling::default());
+        let row = game.current_shape().pattern_top_left_row;
+
+        // 20 rows a second, the first one right away
+        for _ in 0..30 {
+            game.update(DOWN, FRAME);
+        }
+        assert_eq!(game.current_shape().pattern_top_left_row, row + 10);
+    }
+
+    #[test]
+    fn infinite_sdf_drops_to_the_floor_without_locking() {
+        let mut game = game_with_handling(Handling { soft_drop_factor: f64::INFINITY, ..Handling::default() });
+
+        assert!(game.update(DOWN, FRAME).is_empty());
+        assert_eq!(game.current_shape().positions, game.ghost_positions());
+    }
+
+    #[test]
+    fn zero_arr_shifts_to_the_wall_once_das_charges() {
+        let mut game = game_with_handling(Handling { arr: 0.0, ..Handling::default() });
+
+        game.update(RIGHT, FRAME);
+        assert_eq!(col(&game), 4);
+
+        for _ in 0..10 {
+            game.update(RIGHT, FRAME);
+        }
+        assert_eq!(col(&game), 4);
+
+        game.update(RIGHT, FRAME);
+        assert_eq!(col(&game), 6);
+    }
+
+    #[test]
+    fn das_cut_pauses_repeats_after_a_rotation() {
+        let mut game = game_with_handling(Handling { das_cut_delay: 0.1, ..Handling::default() });
+        for _ in 0..12 {
+            game.update(RIGHT, FRAME);
+        }
+        assert_eq!(col(&game), 5);
+
+        game.update(Input { right: true, rotate_clockwise: true, ..NOTHING }, FRAME);
+        let turned = col(&game);
+        for _ in 0..5 {
+            game.update(RIGHT, FRAME);
+            assert_eq!(col(&game), turned);
+        }
+
+        for _ in 0..4 {
+            game.update(RIGHT, FRAME);
+        }
+        assert!(col(&game) > turned);
+    }
+
+    #[test]
+    fn das_cut_pauses_repeats_for_a_new_piece() {
+        let mut game = game_with_handling(Handling { arr: 0.0, das_cut_delay: 0.1, ..Handling::default() });
+        for _ in 0..12 {
+            game.update(RIGHT, FRAME);
+        }
+        assert_eq!(col(&game), 6);
+
+        // the next I comes in with right still held
+        game.update(Input { right: true, hard_drop: true, ..NOTHING }, FRAME);
+        assert_eq!(col(&game), 3);
+        for _ in 0..5 {
+            game.update(RIGHT, FRAME);
+            assert_eq!(col(&game), 3);
+        }
+
+        for _ in 0..2 {
+            game.update(RIGHT, FRAME);
+        }
+        assert_eq!(col(&game), 6);
+    }
+
+    #[test]
+    fn releasing_the_newer_direction_hands_back_to_the_older() {
+        let mut game = game_with_handling(Handling::default());
+
+        game.update(LEFT, FRAME);
+        assert_eq!(col(&game), 2);
+
+        // right is newer, so it wins while both are held
+        let both = Input { left: true, right: true, ..NOTHING };
+        game.update(both, FRAME);
+        assert_eq!(col(&game), 3);
+        for _ in 0..5 {
+            game.update(both, FRAME);
+        }
+        assert_eq!(col(&game), 3);
+
+        // and letting go of it taps left again straight away
+        game.update(LEFT, FRAME);
+        assert_eq!(col(&game), 2);
+    }
+
+    // an O resting on `board` with its top left at (`row`, `col`), under `policy`
+    fn game_with_grounded_o(board: Board, row: i32, col: i32, policy: LockDelayPolicy) -> Game {
+        let mut game = Game::new(Config { lock_delay_policy: policy, ..Config::default() }, 0);
+        game.board = board;
+        game.current_shape = Tetromino::at(3, &game.pieces, &game.board, 0, row, col);
+        game
+    }
+
+    // spins every frame at 60 fps, returning the frame the piece locked on
+    fn spin_until_locked(game: &mut Game, max_frames: usize) -> Option<usize> {
+        (1..=max_frames).find(|frame| {
+            let input = if frame % 2 == 1 { CLOCKWISE } else { COUNTER_CLOCKWISE };
+            game.update(input, 1.0 / 60.0).contains(&Event::PieceLocked { tetromino_type: 3 })
+        })
+    }
+
+    #[test]
+    fn move_reset_locks_once_the_resets_run_out() {
+        let mut game = game_with_grounded_o(Board::default(), 38, 3, LockDelayPolicy::MoveReset(15));
+
+        // the first frame on the ground starts the clock, the next 15 each use up a reset
+        assert_eq!(spin_until_locked(&mut game, 60), Some(16));
+    }
+
+    #[test]
+    fn step_reset_ignores_moves() {
+        let mut game = game_with_grounded_o(Board::default(), 38, 3, LockDelayPolicy::StepReset);
+
+        let frame = spin_until_locked(&mut game, 60).expect("the piece locks");
+        assert!((30..=31).contains(&frame), "locked on frame {}", frame);
+    }
+
+    #[test]
+    fn infinite_never_locks_while_the_piece_moves() {
+        let mut game = game_with_grounded_o(Board::default(), 38, 3, LockDelayPolicy::Infinite);
+        assert_eq!(spin_until_locked(&mut game, 600), None);
+
+        // but standing still, it does
+        let frame = (1..=60).find(|_| !game.update(NOTHING, 1.0 / 60.0).is_empty());
+        assert!(matches!(frame, Some(30..=31)), "locked on frame {:?}", frame);
+    }
+
+    #[test]
+    fn a_new_lowest_row_resets_the_timer_and_the_resets() {
+        // on a one block ledge, a step right drops the O off it
+        let mut game = game_with_grounded_o(Board::from_picture(10, 20, &["....#....."]), 37, 3, LockDelayPolicy::MoveReset(15));
+        assert_eq!(spin_until_locked(&mut game, 10), None);
+        assert_eq!(game.lock_resets, 9);
+
+        game.update(RIGHT, 1.0 / 60.0);
+        for _ in 0..120 {
+            if game.current_shape().pattern_top_left_row == 38 {
+                break;
+            }
+            game.update(NOTHING, 1.0 / 60.0);
+        }
+
+        assert_eq!(game.current_shape().pattern_top_left_row, 38);
+        assert_eq!(game.lock_resets, 0);
+        assert!(game.lock_timer < 0.1);
+
+        // the landing frame already started the clock, all 15 resets are back
+        assert_eq!(spin_until_locked(&mut game, 60), Some(15));
+    }
+
+    // rotates `tetromino` on `board` the way a game does, then where it ended up, as (rotation, row, col)
+    fn rotate(board: &Board, tetromino: &Tetromino, clockwise: bool) -> (u8, i32, i32) {
+        let mut game = Game::new(Config::default(), 0);
+        game.board = board.clone();
+        game.current_shape = tetromino.clone();
+        game.rotate_current_shape(clockwise);
+
+        let shape = game.current_shape();
+        (shape.rotation_pattern_index, shape.pattern_top_left_row, shape.pattern_top_left_col)
+    }
+
+    #[test]
+    fn i_kicks_off_the_walls() {
+        let pieces = Config::default().piece_set();
+        let board = Board::default();
+
+        // standing in the first column
+        let left = Tetromino::at(0, &pieces, &board, 3, 10, -1);
+        assert_eq!(rotate(&board, &left, true), (0, 10, 0));
+        assert_eq!(rotate(&board, &left, false), (2, 10, 0));
+
+        // standing in the last column
+        let right = Tetromino::at(0, &pieces, &board, 1, 10, 7);
+        assert_eq!(rotate(&board, &right, true), (2, 10, 6));
+        assert_eq!(rotate(&board, &right, false), (0, 10, 6));
+    }
+
+    #[test]
+    fn jlstz_kick_off_the_walls() {
+        let pieces = Config::default().piece_set();
+        let board = Board::default();
+
+        let left = Tetromino::at(T_TETROMINO, &pieces, &board, 1, 10, -1);
+        assert_eq!(rotate(&board, &left, true), (2, 10, 0));
+        assert_eq!(rotate(&board, &left, false), (0, 10, 0));
+
+        let right = Tetromino::at(T_TETROMINO, &pieces, &board, 3, 10, 8);
+        assert_eq!(rotate(&board, &right, true), (0, 10, 7));
+        assert_eq!(rotate(&board, &right, false), (2, 10, 7));
+    }
+
+    #[test]
+    fn i_kicks_up_off_the_stack() {
+        let pieces = Config::default().piece_set();
+        // lying on the stack, standing up would go through it
+        let board = Board::from_picture(10, 20, &["#########."]);
+        let lying = Tetromino::at(0, &pieces, &board, 0, 37, 3);
+        assert_eq!(rotate(&board, &lying, true), (1, 35, 4));
+        assert_eq!(rotate(&board, &lying, false), (3, 35, 2));
+    }
+
+    #[test]
+    fn jlstz_kick_up_off_the_stack() {
+        let pieces = Config::default().piece_set();
+        // flat side down on the stack, pointing down would go through it
+        let board = Board::from_picture(10, 20, &["#########."]);
+        let t = Tetromino::at(T_TETROMINO, &pieces, &board, 0, 37, 3);
+        assert_eq!(rotate(&board, &t, true), (1, 36, 2));
+        assert_eq!(rotate(&board, &t, false), (3, 36, 4));
+    }
+
+    #[test]
+    fn nothing_fits_nothing_turns() {
+        let pieces = Config::default().piece_set();
+        // a flat I in a one row gap, with the floor right below
+        let board = Board::from_picture(10, 20, &[
+            "..........",
+            "#########.",
+            "..........",
+            "#########.",
+        ]);
+        let i = Tetromino::at(0, &pieces, &board, 0, 37, 0);
+
+        assert_eq!(rotate(&board, &i, true), (0, 37, 0));
+        assert_eq!(rotate(&board, &i, false), (0, 37, 0));
+    }
+}
@@ -0,0 +1,201 @@
+// Save files: everything about a game in progress, so it can be picked up again
+// exactly where it was left. Laid out as described in `bytes.rs`, with the replay
+// so far first (which brings the seed and config along), then the state on top.
+// Bump the version whenever any of this changes.
+
+use std::path::Path;
+
+use super::{spawn_shape, Game, TopOut};
+use crate::bytes::{self, Reader, Writer};
+use crate::handling::AutoShift;
+use crate::input::Input;
+use crate::replay::Replay;
+use crate::rng::Rng;
+use crate::scoring::Scoring;
+use crate::tetromino::Tetromino;
+
+// ----
+
+const MAGIC: &[u8; 4] = b"TRSS";
+pub const SAVE_FORMAT_VERSION: u16 = 1;
+
+// ----
+
+impl Game {
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_save_bytes()?).map_err(|error| format!("couldn't write {}: {}", path.display(), error))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|error| format!("couldn't read {}: {}", path.display(), error))?;
+        Self::from_save_bytes(&bytes).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    pub fn to_save_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut result = Ok(());
+        let bytes = bytes::seal(MAGIC, SAVE_FORMAT_VERSION, |writer| {
+            result = self.replay.write(writer);
+            self.write_state(writer);
+        });
+
+        result.map(|()| bytes)
+    }
+
+    /// A game which carries on exactly as the saved one would have.
+    pub fn from_save_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = bytes::unseal(bytes, MAGIC, SAVE_FORMAT_VERSION, "save")?;
+        let replay = Replay::read(&mut reader)?;
+
+        // the config and seed set up everything the state doesn't cover, like the pieces
+        let mut game = Self::new(replay.config().clone(), replay.seed());
+        game.read_state(&mut reader)?;
+        game.replay = replay;
+        reader.finish()?;
+
+        Ok(game)
+    }
+
+    fn write_state(&self, writer: &mut Writer) {
+        writer.u64(self.rng.state());
+        writer.byte_list(&self.randomizer.state());
+        self.board.write(writer);
+
+        let shape = &self.current_shape;
+        writer.u8(shape.tetromino_type);
+        writer.u8(shape.rotation_pattern_index);
+        writer.i32(shape.pattern_top_left_row);
+        writer.i32(shape.pattern_top_left_col);
+
+        // queued and held pieces are always in their spawn position, so their type says it all
+        let next_types: Vec<u8> = self.next_queue.iter().map(|tetromino| tetromino.tetromino_type).collect();
+        writer.byte_list(&next_types);
+        writer.bool(self.hold_shape.is_some());
+        writer.u8(self.hold_shape.as_ref().map_or(0, |tetromino| tetromino.tetromino_type));
+        writer.bool(self.hold_used);
+
+        writer.bool(self.last_rotation_kick.is_some());
+        writer.u32(self.last_rotation_kick.unwrap_or(0) as u32);
+        writer.f64(self.lock_timer);
+        writer.u32(self.lock_resets);
+        writer.i32(self.lowest_row);
+        self.scoring.write(writer);
+
+        writer.f64(self.time);
+        writer.f64(self.gravity_timer);
+        writer.i32(self.shift_direction);
+        self.left_shift.write(writer);
+        self.right_shift.write(writer);
+        writer.f64(self.soft_drop_timer);
+        writer.f64(self.das_cut_timer);
+
+        writer.u32(self.clearing_rows.len() as u32);
+        for &row in self.clearing_rows.iter() {
+            writer.u32(row as u32);
+        }
+        writer.f64(self.line_clear_timer);
+        writer.u8(TopOut::to_byte(self.top_out));
+        writer.u8(self.previous_input.to_bits());
+    }
+
+    fn read_state(&mut self, reader: &mut Reader) -> Result<(), String> {
+        self.rng = Rng::new(reader.u64()?);
+        self.randomizer.restore(reader.byte_list()?)?;
+        self.board.read(reader)?;
+
+        let tetromino_type = self.read_tetromino_type(reader)?;
+        let rotation_pattern_index = reader.u8()?;
+        if rotation_pattern_index >= self.pieces.rotation_count(tetromino_type) {
+            return Err(format!("piece {} has no rotation state {}", tetromino_type, rotation_pattern_index));
+        }
+        let row = reader.i32()?;
+        let col = reader.i32()?;
+        let pattern = self.pieces.pattern(tetromino_type, rotation_pattern_index);
+        self.current_shape = Tetromino {
+            tetromino_type,
+            positions: Tetromino::transfer_shape_pattern_to_positions(&self.board, pattern, row, col).ok_or("the current piece is off the board")?,
+            rotation_pattern_index,
+            pattern_top_left_row: row,
+            pattern_top_left_col: col,
+        };
+
+        let next_types = reader.byte_list()?;
+        self.next_queue.clear();
+        for &tetromino_type in next_types {
+            if tetromino_type >= self.pieces.len() {
+                return Err(format!("there is no piece {}", tetromino_type));
+            }
+            self.next_queue.push_back(spawn_shape(tetromino_type, &self.pieces, &self.board));
+        }
+
+        let has_hold = reader.bool()?;
+        let hold_type = self.read_tetromino_type(reader)?;
+        self.hold_shape = if has_hold { Some(spawn_shape(hold_type, &self.pieces, &self.board)) } else { None };
+        self.hold_used = reader.bool()?;
+
+        let has_kick = reader.bool()?;
+        let kick = reader.u32()? as usize;
+        self.last_rotation_kick = if has_kick { Some(kick) } else { None };
+        self.lock_timer = reader.f64()?;
+        self.lock_resets = reader.u32()?;
+        self.lowest_row = reader.i32()?;
+        self.scoring = Scoring::read(reader)?;
+
+        self.time = reader.f64()?;
+        self.gravity_timer = reader.f64()?;
+        self.shift_direction = reader.i32()?.clamp(-1, 1);
+        self.left_shift = AutoShift::read(reader)?;
+        self.right_shift = AutoShift::read(reader)?;
+        self.soft_drop_timer = reader.f64()?;
+        self.das_cut_timer = reader.f64()?;
+
+        self.clearing_rows.clear();
+        for _ in 0..reader.u32()? {
+            let row = reader.u32()? as usize;
+            if row >= self.board.total_rows() {
+                return Err(format!("row {} is off the board", row));
+            }
+            self.clearing_rows.push(row);
+        }
+        self.line_clear_timer = reader.f64()?;
+        self.top_out = TopOut::from_byte(reader.u8()?)?;
+        self.previous_input = Input::from_bits(reader.u8()?);
+
+        Ok(())
+    }
+
+    fn read_tetromino_type(&self, reader: &mut Reader) -> Result<u8, String> {
+        let tetromino_type = reader.u8()?;
+        if tetromino_type >= self.pieces.len() {
+            return Err(format!("there is no piece {}", tetromino_type));
+        }
+
+        Ok(tetromino_type)
+    }
+}
+
+// ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::{mash, mashed_game};
+    use crate::replay_file::ReplaySummary;
+
+    #[test]
+    fn loaded_games_carry_on_the_same() {
+        let mut game = mashed_game(17, 300);
+        assert!(!game.is_game_over());
+        assert!((0..game.board().cell_count()).any(|index| game.board().is_occupied(index)), "nothing locked yet");
+
+        let mut loaded = Game::from_save_bytes(&game.to_save_bytes().unwrap()).unwrap();
+        assert_eq!(ReplaySummary::of(&loaded), ReplaySummary::of(&game));
+        assert_eq!(loaded.replay(), game.replay());
+
+        mash(&mut game, 300..900);
+        mash(&mut loaded, 300..900);
+        assert_eq!(ReplaySummary::of(&loaded), ReplaySummary::of(&game));
+        assert_eq!(loaded.to_save_bytes(), game.to_save_bytes());
+    }
+}
@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+
+use crate::bytes::{Reader, Writer};
+
+// ----
+
+/// How held buttons turn into movement. Times are in seconds.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Handling {
+    /// Delayed Auto Shift: how long left or right must be held before it repeats.
+    pub das: f64,
+    /// Auto Repeat Rate: time between repeats once DAS is charged. 0 goes straight to the wall.
+    pub arr: f64,
+    /// Soft Drop Factor: soft drop speed as a multiple of gravity. Infinity drops straight to the floor.
+    pub soft_drop_factor: f64,
+    /// DAS cut: auto repeat pauses this long after a rotation, hold or new piece. 0 turns it off.
+    pub das_cut_delay: f64,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Self {
+            das: 0.167,
+            arr: 0.033,
+            soft_drop_factor: 20.0,
+            das_cut_delay: 0.0,
+        }
+    }
+}
+
+// ----
+
+/// Auto repeat state for one held direction.
+#[derive(Copy, Clone, Default)]
+pub(crate) struct AutoShift {
+    held: bool,
+    held_time: f64,
+    repeat_timer: f64,
+}
+
+impl AutoShift {
+    pub(crate) fn release(&mut self) {
+        *self = Self::default();
+    }
+
+    pub(crate) fn write(&self, writer: &mut Writer) {
+        writer.bool(self.held);
+        writer.f64(self.held_time);
+        writer.f64(self.repeat_timer);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self, String> {
+        Ok(Self {
+            held: reader.bool()?,
+            held_time: reader.f64()?,
+            repeat_timer: reader.f64()?,
+        })
+    }
+
+    /// How many cells to move this frame. `u32::MAX` means as far as possible.
+    pub(crate) fn update(&mut self, dt: f64, handling: &Handling, cut: bool) -> u32 {
+        if !self.held {
+            self.held = true;
+            return 1; // the initial tap
+        }
+
+        let previous_held_time = self.held_time;
+        self.held_time += dt;
+
+        if self.held_time < handling.das {
+            return 0;
+        }
+
+        if handling.arr <= 0.0 {
+            return if cut { 0 } else { u32::MAX };
+        }
+
+        if previous_held_time < handling.das {
+            // first repeat lands right as DAS charges, the rest of the frame counts towards the next
+            self.repeat_timer = handling.arr + (self.held_time - handling.das);
+        }
+        else {
+            self.repeat_timer += dt;
+        }
+
+        let steps = (self.repeat_timer / handling.arr).floor();
+        self.repeat_timer -= steps * handling.arr;
+
+        if cut {
+            return 0;
+        }
+
+        steps as u32
+    }
+}
+
+// ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: f64 = 1.0 / 60.0;
+
+    // cells moved so far after each frame of holding, never cut
+    fn held_for(handling: &Handling, frames: usize) -> Vec<u32> {
+        let mut shift = AutoShift::default();
+        let mut moved = 0;
+        (0..frames).map(|_| {
+            moved += shift.update(FRAME, handling, false);
+            moved
+        }).collect()
+    }
+
+    #[test]
+    fn das_waits_before_the_arr_repeats() {
+        let moved = held_for(&Handling::default(), 60);
+
+        // the tap, then nothing until DAS charges at 0.167 s, then a cell every 0.033 s
+        assert_eq!(moved[..14], [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 3]);
+        assert_eq!(moved[59], 1 + 1 + ((59.0 * FRAME - 0.167) / 0.033) as u32);
+    }
+
+    #[test]
+    fn zero_arr_goes_straight_to_the_wall() {
+        let handling = Handling { arr: 0.0, ..Handling::default() };
+        let mut shift = AutoShift::default();
+
+        assert_eq!(shift.update(FRAME, &handling, false), 1);
+        for _ in 0..10 {
+            assert_eq!(shift.update(FRAME, &handling, false), 0);
+        }
+        assert_eq!(shift.update(FRAME, &handling, false), u32::MAX);
+    }
+
+    #[test]
+    fn a_cut_holds_back_repeats_but_keeps_das_charged() {
+        for arr in [0.0, 0.033] {
+            let handling = Handling { arr, ..Handling::default() };
+            let mut shift = AutoShift::default();
+            for _ in 0..20 {
+                shift.update(FRAME, &handling, false);
+            }
+
+            assert_eq!(shift.update(FRAME, &handling, true), 0);
+            assert_eq!(shift.update(FRAME, &handling, true), 0);
+            assert!(shift.update(FRAME * 3.0, &handling, false) > 0, "arr {}", arr);
+        }
+    }
+
+    #[test]
+    fn releasing_starts_over_with_a_tap() {
+        let handling = Handling::default();
+        let mut shift = AutoShift::default();
+        for _ in 0..30 {
+            shift.update(FRAME, &handling, false);
+        }
+
+        shift.release();
+        assert_eq!(shift.update(FRAME, &handling, false), 1);
+        assert_eq!(shift.update(FRAME, &handling, false), 0);
+    }
+}
@@ -0,0 +1,38 @@
+/// Buttons held down during a single update. The engine tracks the previous
+/// frame itself, so frontends only need to report what is currently down.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct Input {
+    pub left: bool,
+    pub right: bool,
+    pub down: bool,
+    pub rotate_clockwise: bool,
+    pub rotate_counter_clockwise: bool,
+    pub hold: bool,
+    pub hard_drop: bool,
+    pub sonic_drop: bool,
+}
+
+impl Input {
+    /// One bit per button, in field order, for storing a frame in a byte.
+    pub fn to_bits(self) -> u8 {
+        [self.left, self.right, self.down, self.rotate_clockwise, self.rotate_counter_clockwise, self.hold, self.hard_drop, self.sonic_drop]
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (bit, &down)| bits | (down as u8) << bit)
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        let down = |bit: u8| bits & (1 << bit) != 0;
+
+        Self {
+            left: down(0),
+            right: down(1),
+            down: down(2),
+            rotate_clockwise: down(3),
+            rotate_counter_clockwise: down(4),
+            hold: down(5),
+            hard_drop: down(6),
+            sonic_drop: down(7),
+        }
+    }
+}
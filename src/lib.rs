@@ -0,0 +1,35 @@
+//! The rules of Tetris, without a window. `src/main.rs` is one frontend for
+//! this; tests, bots and servers can drive a `Game` just as well. Build with
+//! `--no-default-features` to leave the frontend, and its windowing and audio
+//! dependencies, out.
+
+mod board;
+mod bytes;
+mod config;
+mod game;
+mod handling;
+mod hash;
+mod input;
+mod piece_set;
+pub mod randomizer;
+mod replay;
+mod replay_file;
+mod rng;
+pub mod rotation;
+mod scoring;
+mod tetromino;
+mod tspin;
+
+pub use board::{Board, RowMask, DEFAULT_HEIGHT, DEFAULT_WIDTH, MAX_BOARD_WIDTH};
+pub use config::{problem, Config, LockDelayPolicy, MAX_NEXT_QUEUE_LENGTH, MIN_BOARD_SIZE};
+pub use game::{Event, Game, TopOut, SAVE_FORMAT_VERSION};
+pub use handling::Handling;
+pub use input::Input;
+pub use piece_set::{KickTable, Pattern, PieceDefinition, PieceSet};
+pub use randomizer::{Randomizer, RandomizerKind};
+pub use replay::{Frame, Playback, Replay};
+pub use replay_file::{ReplayFile, ReplaySummary, REPLAY_FORMAT_VERSION};
+pub use rng::Rng;
+pub use rotation::{RotationSystem, RotationSystemKind};
+pub use scoring::{ClearScore, ClearType, Scoring, TSpin};
+pub use tetromino::Tetromino;
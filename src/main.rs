@@ -1,710 +1,680 @@
+mod settings;
+
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
 use macroquad::prelude::*;
-use ::rand::Rng;
-use ::rand::thread_rng;
+use tetris_rs::{Config, Event, Game, Input, PieceSet, Playback, ReplayFile, ReplaySummary, Tetromino};
+
+use settings::{any_key_down, any_key_pressed, to_color, user_dir, Settings, CONTINUE_KEY, GHOST_KEY, NEW_GAME_KEY, QUIT_KEY, RESTART_KEY, SAVE_REPLAY_KEY, WATCH_REPLAY_KEY};
 
 // ----
 
-const WINDOW_WIDTH: i32 = 500;
-const WINDOW_HEIGHT: i32 = 800;
-const GRID_WIDTH: u8 = 10;
-const GRID_HEIGHT: u8 = 20;
-const BLOCK_SIZE: u8 = 32;
-const TICKS_PER_SECOND: f64 = 1.0;
+const FIELD_MARGIN: f32 = 80.0; // above and below the board
+const PREVIEW_BLOCK_SIZE: u8 = 20;
+const ACTION_TEXT_SECONDS: f32 = 1.5;
+const PLAYBACK_SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const NORMAL_PLAYBACK_SPEED: usize = 2; // index into PLAYBACK_SPEEDS
+const SCRUB_SECONDS: f64 = 5.0;
+const SAVE_FILE_NAME: &str = "tetris_rs.save"; // in user_dir(), or the working directory without one
+const AUTOSAVE_SECONDS: f32 = 5.0; // the window's close button can't be caught, so don't lose more than this
+const PROBLEM_SECONDS: f32 = 10.0; // how long problems with the settings stay up
 
 // ----
 
-fn window_conf() -> Conf {
+fn window_conf(settings: &Settings) -> Conf {
     Conf {
         window_title: "Tetris.rs".to_owned(),
         fullscreen: false,
         window_resizable: false,
-        window_width: WINDOW_WIDTH,
-        window_height: WINDOW_HEIGHT,
+        window_width: settings.window_width,
+        window_height: settings.window_height,
         ..Default::default()
     }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() {
-    let mut app = App::new();
-
-    loop {
-        clear_background(Color::new(0.10, 0.10, 0.10, 1.0));
-
-        app.update();
-        app.draw();
-
-        next_frame().await
-    }
+// usage: tetris_rs [--config FILE.toml] [--replay FILE.rep] [--rotation srs|ars|nes|classic|pentomino|polyomino] [--pieces FILE.toml] [--randomizer 7-bag|14-bag|tgm1|tgm2|nes|random] [--seed N]
+//                  [--level N] [--next 0-6] [--lock-delay SECONDS] [--lock-policy infinite|move|move:N|step]
+//                  [--line-clear-delay SECONDS] [--partial-lock-out] [--width N] [--height N]
+//                  [--das SECONDS] [--arr SECONDS] [--sdf FACTOR|inf] [--das-cut SECONDS] [--no-ghost]
+//        settings are read from --config, or config.toml in the user's config dir (see config.example.toml), and flags override them
+//        a game in progress is saved on pausing and every few seconds, and offered again on the next launch
+struct Args {
+    config: Config,
+    seed: Option<u64>,
+    replay: Option<ReplayFile>, // watched first, in front of the saved or new game
+    settings: Settings,
+    problems: Vec<String>, // with the settings file and flags, each already fixed up
 }
 
-// ----
-
-#[derive(Clone)]
-struct Block {
-    index: u8,
-    tetromino_type: u8, // for determining draw color
-    occupied: bool,
-    col: u8,
-    row: u8,
+fn next_value<T>(args: &mut impl Iterator<Item = String>, flag: &str) -> Option<T> where T: FromStr, T::Err: Display {
+    match args.next().map(|value| value.parse()) {
+        Some(Ok(value)) => Some(value),
+        Some(Err(error)) => {
+            eprintln!("invalid value for {}: {}", flag, error);
+            None
+        },
+        None => {
+            eprintln!("{} needs a value", flag);
+            None
+        },
+    }
 }
 
-impl Block {
-    fn new() -> Self {
-        Self {
-            index: 0,
-            tetromino_type: 0,
-            occupied: false,
-            col: 0,
-            row: 0
+fn parse_args() -> Args {
+    // the settings come first so the rest of the flags can override them
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config_path = args.iter().position(|arg| arg == "--config").and_then(|i| args.get(i + 1)).map(PathBuf::from);
+    let (settings, problems) = Settings::load(config_path.as_deref());
+
+    let mut parsed = Args {
+        config: settings.game.clone(),
+        seed: None,
+        replay: None,
+        settings,
+        problems,
+    };
+    let config = &mut parsed.config;
+    let mut board_width = None; // otherwise the settings' width, or whatever suits the pieces
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let flag = arg.as_str();
+        match flag {
+            "--config" => {
+                args.next(); // already loaded
+            },
+            "--rotation" => config.rotation_system = next_value(&mut args, flag).unwrap_or(config.rotation_system),
+            "--replay" => match args.next().map(ReplayFile::load) {
+                Some(Ok(replay)) => parsed.replay = Some(replay),
+                Some(Err(error)) => eprintln!("{}", error),
+                None => eprintln!("{} needs a value", flag),
+            },
+            "--pieces" => match args.next().map(PieceSet::load) {
+                Some(Ok(pieces)) => config.pieces = Some(pieces),
+                Some(Err(error)) => eprintln!("{}", error),
+                None => eprintln!("{} needs a value", flag),
+            },
+            "--randomizer" => config.randomizer = next_value(&mut args, flag).unwrap_or(config.randomizer),
+            "--level" => config.start_level = next_value(&mut args, flag).unwrap_or(config.start_level),
+            "--width" => board_width = next_value(&mut args, flag).or(board_width),
+            "--height" => config.board_height = next_value(&mut args, flag).unwrap_or(config.board_height),
+            "--next" => config.next_queue_length = next_value(&mut args, flag).unwrap_or(config.next_queue_length),
+            "--lock-delay" => config.lock_delay = next_value(&mut args, flag).unwrap_or(config.lock_delay),
+            "--lock-policy" => config.lock_delay_policy = next_value(&mut args, flag).unwrap_or(config.lock_delay_policy),
+            "--line-clear-delay" => config.line_clear_delay = next_value(&mut args, flag).unwrap_or(config.line_clear_delay),
+            "--partial-lock-out" => config.partial_lock_out = true,
+            "--das" => config.handling.das = next_value(&mut args, flag).unwrap_or(config.handling.das),
+            "--arr" => config.handling.arr = next_value(&mut args, flag).unwrap_or(config.handling.arr),
+            "--sdf" => config.handling.soft_drop_factor = next_value(&mut args, flag).unwrap_or(config.handling.soft_drop_factor),
+            "--das-cut" => config.handling.das_cut_delay = next_value(&mut args, flag).unwrap_or(config.handling.das_cut_delay),
+            "--seed" => parsed.seed = next_value(&mut args, flag).or(parsed.seed),
+            "--no-ghost" => parsed.settings.visuals.show_ghost = false,
+            _ => eprintln!("ignoring unknown argument '{}'", arg),
         }
     }
-}
 
-// ----
+    config.board_width = match board_width {
+        Some(board_width) => board_width,
+        None if parsed.settings.board_width_given => config.board_width,
+        None => config.rotation_system.default_board_width(),
+    };
 
-#[derive(PartialEq, Eq)]
-enum CollisionType {
-    None,
-    Wall,
-    Ground,
-    Block
+    // flags can be out of range too
+    parsed.problems.extend(config.sanitize());
+    parsed.problems.extend(parsed.settings.check_piece_colors(&config.piece_set()));
+    parsed
 }
 
-// ----
+fn save_path() -> PathBuf {
+    user_dir().unwrap_or_default().join(SAVE_FILE_NAME)
+}
 
-#[derive(Copy, Clone)]
-struct Tetromino {
-    tetromino_type: u8,
-    positions: [u8; 4],
-    rotation_patterns: [[[u8; 4]; 4]; 4],
-    rotation_pattern_index: u8,
-    pattern_top_left_row: i32,
-    pattern_top_left_col: i32,
-    color: Color,
+fn main() {
+    let args = parse_args();
+    for problem in args.problems.iter() {
+        eprintln!("{}", problem);
+    }
+
+    macroquad::Window::from_config(window_conf(&args.settings), run(args));
 }
 
-impl Tetromino {
-    fn new(tetromino_type: u8) -> Self {
-        let color;
-        let positions;
-        let rotation_patterns;
-
-        match tetromino_type {
-            0 => { // I_SHAPE
-                rotation_patterns = [
-                    [
-                        [0, 1, 0, 0],
-                        [0, 1, 0, 0],
-                        [0, 1, 0, 0],
-                        [0, 1, 0, 0],
-                    ],
-
-                    [
-                        [0, 0, 0, 0],
-                        [1, 1, 1, 1],
-                        [0, 0, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 1, 0, 0],
-                        [0, 1, 0, 0],
-                        [0, 1, 0, 0],
-                        [0, 1, 0, 0],
-                    ],
-
-                    [
-                        [0, 0, 0, 0],
-                        [1, 1, 1, 1],
-                        [0, 0, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-                ];
-
-                color = PINK;
-            },
+async fn run(args: Args) {
+    let new_game = Game::new(args.config, args.seed.unwrap_or_else(::rand::random));
+    let settings = args.settings;
+    let mut problems = args.problems;
+
+    // no save is nothing to mention, one which won't load is
+    let save_path = save_path();
+    let saved_game = match Game::load(&save_path) {
+        Ok(saved_game) => Some(saved_game).filter(|saved_game| !saved_game.is_game_over()),
+        Err(_) if !save_path.exists() => None,
+        Err(error) => {
+            problems.push(format!("couldn't continue the saved game, {}", error));
+            None
+        },
+    };
+
+    // a saved game waits behind a replay, and is offered once it's over
+    let mut app = match (saved_game, &args.replay) {
+        (Some(saved_game), _) => App::offer_continue(saved_game, new_game, settings),
+        (None, Some(file)) => App::new(Game::new(file.replay.config().clone(), file.replay.seed()), settings),
+        (None, None) => App::new(new_game, settings),
+    };
+    if let Some(file) = args.replay {
+        app.watch(file);
+    }
+    app.problems = problems;
 
-            1 => { // J_SHAPE
-                rotation_patterns = [
-                    [
-                        [0, 0, 1, 0],
-                        [0, 0, 1, 0],
-                        [0, 1, 1, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 1, 0, 0],
-                        [0, 1, 1, 1],
-                        [0, 0, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 0, 1, 1],
-                        [0, 0, 1, 0],
-                        [0, 0, 1, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 0, 0, 0],
-                        [0, 1, 1, 1],
-                        [0, 0, 0, 1],
-                        [0, 0, 0, 0],
-                    ],
-                ];
-
-                color = YELLOW;
-            },
+    loop {
+        clear_background(to_color(app.settings.visuals.background));
 
-            2 => { // L_SHAPE
-                rotation_patterns = [
-                    [
-                        [0, 1, 0, 0],
-                        [0, 1, 0, 0],
-                        [0, 1, 1, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 0, 0, 0],
-                        [1, 1, 1, 0],
-                        [1, 0, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [1, 1, 0, 0],
-                        [0, 1, 0, 0],
-                        [0, 1, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 0, 1, 0],
-                        [1, 1, 1, 0],
-                        [0, 0, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-                ];
+        if !app.update() {
+            break; // returning closes the window
+        }
+        app.draw();
 
-                color = GREEN;
-            },
+        next_frame().await
+    }
+}
 
-            3 => { // O_SHAPE
-                rotation_patterns = [
-                    [
-                        [0, 1, 1, 0],
-                        [0, 1, 1, 0],
-                        [0, 0, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 1, 1, 0],
-                        [0, 1, 1, 0],
-                        [0, 0, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 1, 1, 0],
-                        [0, 1, 1, 0],
-                        [0, 0, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 1, 1, 0],
-                        [0, 1, 1, 0],
-                        [0, 0, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-                ];
-
-                color = BLUE;
-            },
+// ----
 
-            4 => { // S_SHAPE
-                rotation_patterns = [
-                    [
-                        [0, 1, 1, 0],
-                        [1, 1, 0, 0],
-                        [0, 0, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 1, 0, 0],
-                        [0, 1, 1, 0],
-                        [0, 0, 1, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 1, 1, 0],
-                        [1, 1, 0, 0],
-                        [0, 0, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 1, 0, 0],
-                        [0, 1, 1, 0],
-                        [0, 0, 1, 0],
-                        [0, 0, 0, 0],
-                    ],
-                ];
-
-                color = DARKPURPLE;
-            },
+struct App {
+    game: Game,
+    playback: Option<Playback>, // watching the last game back instead of playing
+    expected_summary: Option<ReplaySummary>, // how the watched game ended when it was recorded
+    playback_speed: usize, // index into PLAYBACK_SPEEDS
+    paused: bool, // the game or the playback
+    new_game: Option<Game>, // while asking whether to continue the saved game instead
+    autosave_timer: f32,
+    settings: Settings,
+    show_ghost: bool,
+    action_text: Vec<String>, // e.g. "TETRIS" and "2 COMBO", shown briefly after a clear
+    action_text_timer: f32,
+    message: String, // e.g. where the replay was saved
+    problems: Vec<String>, // with the settings, shown for a while after starting
+    problem_timer: f32,
+    block_size: f32,
+    x_start: f32,
+    y_start: f32,
+}
 
-            5 => { // Z_SHAPE
-                rotation_patterns = [
-                    [
-                        [0, 1, 1, 0],
-                        [0, 0, 1, 1],
-                        [0, 0, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 0, 0, 1],
-                        [0, 0, 1, 1],
-                        [0, 0, 1, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 1, 1, 0],
-                        [0, 0, 1, 1],
-                        [0, 0, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 0, 0, 1],
-                        [0, 0, 1, 1],
-                        [0, 0, 1, 0],
-                        [0, 0, 0, 0],
-                    ],
-                ];
-
-                color = ORANGE;
-            },
+impl App {
+    fn new(game: Game, settings: Settings) -> Self {
+        let mut app = Self {
+            game,
+            playback: None,
+            expected_summary: None,
+            playback_speed: NORMAL_PLAYBACK_SPEED,
+            paused: false,
+            new_game: None,
+            autosave_timer: 0.0,
+            show_ghost: settings.visuals.show_ghost,
+            settings,
+            action_text: Vec::new(),
+            action_text_timer: 0.0,
+            message: String::new(),
+            problems: Vec::new(),
+            problem_timer: PROBLEM_SECONDS,
+            block_size: 0.0,
+            x_start: 0.0,
+            y_start: 0.0,
+        };
+
+        app.fit_board();
+        app
+    }
 
-            6 => { // T_SHAPE
-                rotation_patterns = [
-                    [
-                        [0, 1, 0, 0],
-                        [1, 1, 1, 0],
-                        [0, 0, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 1, 0, 0],
-                        [0, 1, 1, 0],
-                        [0, 1, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 0, 0, 0],
-                        [1, 1, 1, 0],
-                        [0, 1, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-
-                    [
-                        [0, 1, 0, 0],
-                        [1, 1, 0, 0],
-                        [0, 1, 0, 0],
-                        [0, 0, 0, 0],
-                    ],
-                ];
-
-                color = BEIGE;
-            },
+    // starts out paused on a saved game, asking whether to carry on with it or start `new_game`
+    fn offer_continue(saved_game: Game, new_game: Game, settings: Settings) -> Self {
+        let mut app = Self::new(saved_game, settings);
+        app.new_game = Some(new_game);
+        app.paused = true;
+        app
+    }
 
-            _ => {
-                panic!("invalid input to tetromino new, {}", tetromino_type);
-            }
-        }
+    // watches a saved replay, going back to whatever was there before once it's left
+    fn watch(&mut self, file: ReplayFile) {
+        self.playback = Some(Playback::new(file.replay));
+        self.expected_summary = Some(file.summary);
+        self.playback_speed = NORMAL_PLAYBACK_SPEED;
+        self.paused = false;
+        self.fit_board();
+    }
 
-        let pattern_top_left_row = 0 as i32;
-        let pattern_top_left_col = (GRID_WIDTH/2 - 2) as i32;
-        positions = Tetromino::transfer_shape_pattern_to_positions(&rotation_patterns[0], pattern_top_left_row, pattern_top_left_col).unwrap();
+    // leave room for the hold and next panels either side, and the sliver of buffer above
+    fn fit_board(&mut self) {
+        let panel_width = 4.0 * PREVIEW_BLOCK_SIZE as f32 + 40.0;
+        let width = self.game().board().width() as f32;
+        let height = self.game().board().height() as f32;
+        let window_width = self.settings.window_width as f32;
+        let window_height = self.settings.window_height as f32;
+
+        self.block_size = self.settings.visuals.max_block_size
+            .min((window_width - 2.0 * panel_width) / width)
+            .min((window_height - 2.0 * FIELD_MARGIN) / height)
+            .floor();
+        self.x_start = window_width / 2.0 - (self.block_size * width) / 2.0;
+        self.y_start = window_height / 2.0 - (self.block_size * height) / 2.0;
+    }
 
-        return Self {
-            tetromino_type,
-            positions,
-            rotation_patterns,
-            pattern_top_left_row,
-            pattern_top_left_col,
-            rotation_pattern_index: 0,
-            color,
+    fn save_game(&mut self) {
+        let path = save_path();
+        let saved = match path.parent() {
+            Some(dir) => std::fs::create_dir_all(dir).map_err(|error| format!("couldn't create {}: {}", dir.display(), error)),
+            None => Ok(()),
+        };
+        if let Err(error) = saved.and_then(|()| self.game.save(&path)) {
+            eprintln!("{}", error);
         }
+        self.autosave_timer = 0.0;
     }
 
-    fn transfer_shape_pattern_to_positions(pattern: &[[u8; 4]; 4], row_offset: i32, col_offset: i32) -> Option<[u8; 4]> {
-        let mut positions = [0,0,0,0];
-        let mut cur_index = 0;
-        for (row_index, row) in pattern.iter().enumerate() {
-            for (col_index, is_occupied) in row.iter().enumerate() {
-                if *is_occupied == 1 {
-                    let desired_index =  Board::get_index(row_index as i32 + row_offset as i32, col_index as i32 + col_offset as i32);
-                    if desired_index.is_some() {
-                        positions[cur_index] = desired_index.unwrap() as u8;
-                        cur_index += 1;
-                    }
-                    else {
-                        return None;
-                    }
-                }
-            }
+    // the one being drawn
+    fn game(&self) -> &Game {
+        match &self.playback {
+            Some(playback) => playback.game(),
+            None => &self.game,
         }
-
-        return Some(positions);
     }
-}
 
-// ----
+    // ----
 
-struct Board {
-    grid: Vec<Block>,
-    x_start: f32,
-    y_start: f32,
-}
+    // false once it's time to quit
+    fn update(&mut self) -> bool {
+        if is_key_pressed(GHOST_KEY) {
+            self.show_ghost = !self.show_ghost;
+        }
 
-impl Board {
-    fn new() -> Self {
-        let mut grid = vec![Block::new(); (GRID_WIDTH*GRID_HEIGHT) as usize];
+        self.action_text_timer -= get_frame_time();
+        self.problem_timer -= get_frame_time();
 
-        let mut i = 0;
-        for y in 0..GRID_HEIGHT {
-            for x in 0..GRID_WIDTH {
-                grid[i].col = x as u8;
-                grid[i].row = y as u8;
-                grid[i].occupied = false;
-                grid[i].index = i as u8;
+        if self.playback.is_some() {
+            self.update_playback();
+            return true;
+        }
 
-                i += 1;
+        if self.new_game.is_some() {
+            if is_key_pressed(CONTINUE_KEY) {
+                self.new_game = None;
+                self.paused = false;
+            }
+            if is_key_pressed(NEW_GAME_KEY) {
+                self.game = self.new_game.take().unwrap();
+                self.paused = false;
+                self.fit_board();
             }
+            return true;
         }
 
-        Self {
-            grid,
-            x_start: (WINDOW_WIDTH as f32 / 2.0 - (BLOCK_SIZE as f32 *GRID_WIDTH as f32)/2.0) as f32,
-            y_start: WINDOW_HEIGHT as f32 / 2.0 - (BLOCK_SIZE as f32 *GRID_HEIGHT as f32)/2.0,
+        if self.game.is_game_over() {
+            if is_key_pressed(RESTART_KEY) || is_key_pressed(CONTINUE_KEY) {
+                self.game.restart(::rand::random());
+                self.action_text.clear();
+                self.message.clear();
+            }
+            if is_key_pressed(SAVE_REPLAY_KEY) {
+                let path = format!("tetris_rs-{}.rep", self.game.seed());
+                self.message = match ReplayFile::new(&self.game).save(&path) {
+                    Ok(()) => format!("saved {}", path),
+                    Err(error) => error,
+                };
+            }
+            if is_key_pressed(WATCH_REPLAY_KEY) {
+                self.watch(ReplayFile::new(&self.game));
+                self.action_text.clear();
+            }
+            return true;
         }
-    }
 
-    fn get_index(row: i32, col: i32) -> Option<u8> {
-        if row >= 0 {
-            if col >= 0 {
-                if row < GRID_HEIGHT as i32 {
-                    if col < GRID_WIDTH as i32 {
-                        return Some((GRID_WIDTH * row as u8 + col as u8) as u8)
-                    }
-                }
+        // pausing also saves, Q then saves and quits
+        if any_key_pressed(&self.settings.keys.pause) {
+            self.paused = !self.paused;
+            self.save_game();
+        }
+        if self.paused {
+            if is_key_pressed(QUIT_KEY) {
+                self.save_game();
+                return false;
             }
+            return true;
         }
 
-        return None;
-    }
+        let keys = &self.settings.keys;
+        let input = Input {
+            left: any_key_down(&keys.left),
+            right: any_key_down(&keys.right),
+            down: any_key_down(&keys.soft_drop),
+            rotate_clockwise: any_key_down(&keys.rotate_clockwise),
+            rotate_counter_clockwise: any_key_down(&keys.rotate_counter_clockwise),
+            hold: any_key_down(&keys.hold),
+            hard_drop: any_key_down(&keys.hard_drop),
+            sonic_drop: any_key_down(&keys.sonic_drop),
+        };
+
+        let events = self.game.update(input, get_frame_time() as f64);
+        self.show_events(events);
+
+        if self.game.is_game_over() {
+            // nothing left to continue
+            let _ = std::fs::remove_file(save_path());
+            return true;
+        }
 
-    fn get_block_position_from_index(&self, index: u8) -> (f32, f32) {
-        self.get_block_position_from_row_col(self.grid[index as usize].row, self.grid[index as usize].col)
-    }
+        self.autosave_timer += get_frame_time();
+        if self.autosave_timer >= AUTOSAVE_SECONDS {
+            self.save_game();
+        }
 
-    fn get_block_position_from_row_col(&self, row: u8, col: u8) -> (f32, f32) {
-        let x = self.x_start as f32 + col as f32 * BLOCK_SIZE as f32;
-        let y = self.y_start as f32 + row as f32 * BLOCK_SIZE as f32;
-        (x, y)
+        true
     }
 
-    fn is_point_inside_block(&self, (x_point, y_point): (f32, f32), index: u8) -> bool {
-        let (x, y) = self.get_block_position_from_row_col(self.grid[index as usize].row, self.grid[index as usize].col);
+    // space pauses, left and right scrub, up and down change speed, comma and period step while paused
+    fn update_playback(&mut self) {
+        if is_key_pressed(KeyCode::Escape) || is_key_pressed(WATCH_REPLAY_KEY) {
+            self.playback = None;
+            self.action_text.clear();
+            self.paused = self.new_game.is_some(); // back to asking about the saved game
+            self.fit_board();
+            return;
+        }
 
-        if x_point > x {
-            if y_point > y {
-                if x_point < x + BLOCK_SIZE as f32 {
-                    if y_point < y + BLOCK_SIZE as f32 {
-                        return true;
-                    }
-                }
+        if is_key_pressed(KeyCode::Space) {
+            self.paused = !self.paused;
+        }
+        if is_key_pressed(KeyCode::Up) {
+            self.playback_speed = (self.playback_speed + 1).min(PLAYBACK_SPEEDS.len() - 1);
+        }
+        if is_key_pressed(KeyCode::Down) {
+            self.playback_speed = self.playback_speed.saturating_sub(1);
+        }
+
+        let playback = match self.playback.as_mut() {
+            Some(playback) => playback,
+            None => return,
+        };
+
+        let mut events = Vec::new();
+        let scrub = match (is_key_pressed(KeyCode::Left), is_key_pressed(KeyCode::Right)) {
+            (true, false) => -SCRUB_SECONDS,
+            (false, true) => SCRUB_SECONDS,
+            _ => 0.0,
+        };
+
+        if scrub != 0.0 {
+            playback.seek_time(playback.game().time() + scrub);
+            self.action_text_timer = 0.0;
+        }
+        else if self.paused {
+            if is_key_pressed(KeyCode::Period) {
+                events = playback.step();
             }
+            if is_key_pressed(KeyCode::Comma) {
+                playback.seek(playback.frame().saturating_sub(1));
+            }
+        }
+        else {
+            events = playback.advance(get_frame_time() as f64 * PLAYBACK_SPEEDS[self.playback_speed]);
         }
 
-        return false;
+        self.show_events(events);
     }
 
-    fn add_tetromino(&mut self, tetromino: &Tetromino) {
-        for i in 0..4 {
-            let index = tetromino.positions[i];
-            self.grid[index as usize].occupied = true;
-            self.grid[index as usize].tetromino_type = tetromino.tetromino_type;
+    fn show_events(&mut self, events: Vec<Event>) {
+        for event in events {
+            if let Event::Clear { clear_type, score } = event {
+                self.action_text.clear();
+                if score.back_to_back {
+                    self.action_text.push("BACK-TO-BACK".to_owned());
+                }
+                self.action_text.push(clear_type.name().to_owned());
+                if score.combo > 0 {
+                    self.action_text.push(format!("{} COMBO", score.combo));
+                }
+                if score.perfect_clear {
+                    self.action_text.push("PERFECT CLEAR".to_owned());
+                }
+                self.action_text_timer = ACTION_TEXT_SECONDS;
+            }
         }
     }
-}
 
-// ----
-
-struct App {
-    board: Board,
-    next_shape: Tetromino,
-    current_shape: Tetromino,
-    tetromino_types: [Tetromino; 7],
-    next_tick_time: f64,
-    input_debounce_timer: f64,
-    frame_count: u64
-}
+    // ----
 
-impl App {
-    fn new() -> Self {
-        let board = Board::new();
-        let tetromino_types = [
-                Tetromino::new(0),
-                Tetromino::new(1),
-                Tetromino::new(2),
-                Tetromino::new(3),
-                Tetromino::new(4),
-                Tetromino::new(5),
-                Tetromino::new(6)
-            ];
-        let next_shape = Tetromino::new(thread_rng().gen_range(0..7));
-        let current_shape = Tetromino::new(thread_rng().gen_range(0..7));
-
-        Self {
-            board,
-            tetromino_types,
-            next_shape,
-            current_shape,
-            next_tick_time: get_time() + TICKS_PER_SECOND,
-            input_debounce_timer: get_time(),
-            frame_count: 1
-        }
+    fn get_block_position(&self, index: usize) -> (f32, f32) {
+        let (row, col) = self.game().board().row_col(index);
+        let visible_row = row as f32 - self.game().board().hidden_rows() as f32;
+        let x = self.x_start + col as f32 * self.block_size;
+        let y = self.y_start + visible_row * self.block_size;
+        (x, y)
     }
 
-    // ----
+    // the visible field, plus a sliver of the buffer above it
+    fn is_block_drawn(&self, index: usize) -> bool {
+        let (row, _) = self.game().board().row_col(index);
+        row + self.settings.visuals.shown_buffer_rows >= self.game().board().hidden_rows()
+    }
 
-    fn update(&mut self) {
-        self.frame_count += 1;
+    fn is_point_inside_block(&self, (x_point, y_point): (f32, f32), index: usize) -> bool {
+        let (x, y) = self.get_block_position(index);
 
-        let mut x_offset: i32 = 0;
-        let mut y_offset: i32 = 0;
+        x_point > x && y_point > y && x_point < x + self.block_size && y_point < y + self.block_size
+    }
 
-        let cur_time = get_time();
-        if cur_time > self.next_tick_time {
-            self.next_tick_time += TICKS_PER_SECOND;
-            y_offset += 1;
-        }
+    fn piece_color(&self, tetromino_type: u8) -> Color {
+        self.settings.piece_color(self.game().pieces(), tetromino_type)
+    }
 
-        let mut reset_debounce = false;
-        let debounce_time = 0.1;
+    // draws the spawn orientation of a piece, for the side panels, shrunk to fit 4 blocks across
+    fn draw_preview(&self, tetromino: &Tetromino, x: f32, y: f32, color: Color) {
+        let pattern = self.game().pieces().pattern(tetromino.tetromino_type, 0);
+        let block_size = PREVIEW_BLOCK_SIZE as f32 * 4.0 / pattern.len().max(4) as f32;
 
-        if is_key_down(KeyCode::Right) {
-            if cur_time - self.input_debounce_timer > debounce_time {
-                reset_debounce = true;
-                x_offset += 1;
-            }
-        }
-        if is_key_down(KeyCode::Left) {
-            if cur_time - self.input_debounce_timer > debounce_time {
-                reset_debounce = true;
-                x_offset -= 1;
-            }
-        }
-        if is_key_down(KeyCode::Down) {
-            if cur_time - self.input_debounce_timer > debounce_time {
-                reset_debounce = true;
-                y_offset += 1;
+        for (row_index, row) in pattern.iter().enumerate() {
+            for (col_index, &is_occupied) in row.iter().enumerate() {
+                if is_occupied {
+                    let block_x = x + col_index as f32 * block_size;
+                    let block_y = y + row_index as f32 * block_size;
+                    draw_rectangle(block_x, block_y, block_size - 1.0, block_size - 1.0, color);
+                }
             }
         }
+    }
 
-        if is_key_pressed(KeyCode::Up) {
-            let mut desired_positions = [0,0,0,0];
-            let mut collision = false;
-            let mut rotation_index = self.current_shape.rotation_pattern_index + 1;
-            if rotation_index >= 4 {
-                rotation_index = 0;
-            }
-
-            let result = Tetromino::transfer_shape_pattern_to_positions(&self.current_shape.rotation_patterns[rotation_index as usize], self.current_shape.pattern_top_left_row, self.current_shape.pattern_top_left_col);
+    fn draw(&self) {
+        let board = self.game().board();
+        let clearing_rows = self.game().clearing_rows();
 
-            if result.is_some() {
-                desired_positions = result.unwrap();
-            }
-            else {
-                collision = true; // terrible
+        for index in 0..board.cell_count() {
+            let tetromino_type = board.tetromino_type(index);
+            if board.is_hidden(index) && !(tetromino_type.is_some() && self.is_block_drawn(index)) {
+                continue;
             }
 
-            for i in 0..4 {
-                if self.board.grid[desired_positions[i] as usize].occupied {
-                    collision = true;
-                    break;
-                }
+            let mut color = to_color(self.settings.visuals.empty_cell);
+            if let Some(tetromino_type) = tetromino_type {
+                color = self.piece_color(tetromino_type);
             }
-
-            if collision != true {
-                self.current_shape.positions = desired_positions;
-                self.current_shape.rotation_pattern_index = rotation_index;
+            if clearing_rows.contains(&board.row_col(index).0) && (get_time() * 5.0).fract() < 0.5 {
+                color = WHITE; // flash rows about to collapse
+            }
+            if self.is_point_inside_block(mouse_position(), index) {
+                color = GREEN;
             }
-        }
 
-        if y_offset == 2 { // seems reasonable to limit us to 1 vertical movement per tick. this also fixes bug with collision detection reverting back 2 squares instead of (the correct) 1
-            y_offset = 1;
+            let (x, y) = self.get_block_position(index);
+            draw_rectangle(x, y, self.block_size, self.block_size, color);
         }
 
-        if reset_debounce {
-            self.input_debounce_timer = get_time();
+        // no piece in play until the rows collapse
+        if clearing_rows.is_empty() {
+            self.draw_current_shape();
         }
 
-        // segregating into two separate calls so that we can have different behavior for moving left / right and moving vertically (vertically we want to add shape to board on collision)
-        self.move_current_shape(x_offset, 0, false);
-        self.move_current_shape(0, y_offset, true);
-
-        loop {
-            let row = self.should_clear_line();
-            if row != -1 {
-                self.clear_line(row as u8);
-            }
-            else {
-                break;
-            }
-        }
+        self.draw_panels();
     }
 
-    // ----
+    fn draw_current_shape(&self) {
+        let current_shape = self.game().current_shape();
+        if self.show_ghost {
+            let mut color = self.piece_color(current_shape.tetromino_type);
+            color.a = self.settings.visuals.ghost_opacity;
 
-    fn move_current_shape(&mut self, x_offset: i32, y_offset: i32, collision_adds_to_board: bool) {
-        let mut collision = CollisionType::None;
-        let mut desired_positions = [0,0,0,0];
-        for i in 0..4 {
-            let initial_index = self.current_shape.positions[i];
-            let initial_row = self.board.grid[initial_index as usize].row;
-            let initial_col = self.board.grid[initial_index as usize].col;
-            let desired_index = Board::get_index((initial_row as i32 + y_offset) as i32, (initial_col as i32 + x_offset) as i32);
-
-            if desired_index.is_some() {
-                desired_positions[i] = desired_index.unwrap();
+            for &index in self.game().ghost_positions().iter().filter(|&&index| self.is_block_drawn(index)) {
+                let (x, y) = self.get_block_position(index);
+                draw_rectangle(x, y, self.block_size, self.block_size, color);
             }
-            else {
-                if initial_row as i32 + y_offset >= GRID_HEIGHT as i32 {
-                    collision = CollisionType::Ground;
-                }
-                else {
-                    collision = CollisionType::Wall;
-                }
+        }
 
-                break;
-            }
+        for &index in current_shape.positions.iter().filter(|&&index| self.is_block_drawn(index)) {
+            let (x, y) = self.get_block_position(index);
+            let color = self.piece_color(current_shape.tetromino_type);
+            draw_rectangle(x, y, self.block_size, self.block_size, color);
         }
+    }
 
-        if collision == CollisionType::None {
-            for i in 0..4 {
-                let desired_index = desired_positions[i];
-                if self.board.grid[desired_index as usize].occupied {
-                    collision = CollisionType::Block;
-                    break;
-                }
+    fn draw_panels(&self) {
+        let panel_x = self.x_start - 4.0 * PREVIEW_BLOCK_SIZE as f32 - 30.0;
+        draw_text("HOLD", panel_x, self.y_start + 16.0, 24.0, WHITE);
+        if let Some(hold_shape) = self.game().hold_shape() {
+            let mut color = self.piece_color(hold_shape.tetromino_type);
+            if self.game().hold_used() {
+                color = GRAY;
             }
+            self.draw_preview(hold_shape, panel_x, self.y_start + 30.0, color);
         }
 
-        if collision == CollisionType::None {
-            self.current_shape.positions = desired_positions;
-            self.current_shape.pattern_top_left_row += y_offset;
-            self.current_shape.pattern_top_left_col += x_offset;
+        let scoring = self.game().scoring();
+        let hud_y = self.y_start + 30.0 + 4.0 * PREVIEW_BLOCK_SIZE as f32 + 40.0;
+        let stats = [
+            ("SCORE", scoring.score().to_string()),
+            ("LEVEL", scoring.level().to_string()),
+            ("LINES", scoring.lines().to_string()),
+        ];
+        for (i, (label, value)) in stats.iter().enumerate() {
+            let y = hud_y + i as f32 * 60.0;
+            draw_text(label, panel_x, y, 20.0, GRAY);
+            draw_text(value, panel_x, y + 24.0, 28.0, WHITE);
         }
-        else if collision_adds_to_board {
-            if collision == CollisionType::Ground || collision == CollisionType::Block { // wall is fine though i suppose that won't be hit regardless due to the way this is set up
-                self.board.add_tetromino(&self.current_shape);
-                self.current_shape = self.next_shape;
-                self.next_shape = Tetromino::new(thread_rng().gen_range(0..7));
 
-                for i in 0..4 {
-                    if self.board.grid[self.current_shape.positions[i] as usize].occupied {
-                        self.reset_due_to_game_over();
-                    }
-                }
+        if self.action_text_timer > 0.0 {
+            for (i, line) in self.action_text.iter().enumerate() {
+                draw_text(line, panel_x, hud_y + 200.0 + i as f32 * 24.0, 24.0, YELLOW);
             }
         }
-    }
 
-    fn should_clear_line(&mut self) -> i32 {
-        for y in (0..GRID_HEIGHT).rev() {
-            let mut answer = true;
+        let panel_x = self.x_start + self.game().board().width() as f32 * self.block_size + 30.0;
+        if !self.game().next_queue().is_empty() {
+            draw_text("NEXT", panel_x, self.y_start + 16.0, 24.0, WHITE);
+        }
+        for (i, next_shape) in self.game().next_queue().iter().enumerate() {
+            let y = self.y_start + 30.0 + i as f32 * 3.5 * PREVIEW_BLOCK_SIZE as f32;
+            self.draw_preview(next_shape, panel_x, y, self.piece_color(next_shape.tetromino_type));
+        }
 
-            for x in 0..GRID_WIDTH {
-                let index = Board::get_index(y as i32, x as i32).unwrap();
+        let window_height = self.settings.window_height as f32;
+        draw_text(&format!("seed {}", self.game().seed()), 10.0, window_height - 10.0, 20.0, GRAY);
 
-                if self.board.grid[index as usize].occupied != true {
-                    answer = false;
-                    break;
-                }
+        if self.problem_timer > 0.0 {
+            for (i, problem) in self.problems.iter().enumerate() {
+                draw_text(problem, 10.0, 20.0 + i as f32 * 18.0, 16.0, ORANGE);
             }
+        }
 
-            if answer {
-                return y as i32;
-            }
+        if let Some(playback) = &self.playback {
+            self.draw_playback_bar(playback);
         }
 
-        return -1;
+        if let Some(top_out) = self.game().top_out() {
+            self.draw_game_over(top_out.name());
+        }
+        else if self.paused && self.playback.is_none() {
+            self.draw_paused();
+        }
     }
 
-    fn clear_line(&mut self, row: u8) {
-        for x in 0..GRID_WIDTH {
-            let mut y = row;
-            let mut index = Board::get_index(y as i32, x as i32).unwrap();
-            self.board.grid[index as usize].occupied = false; // clear
-
-            while y >= 1 { // move column down
-                y -= 1;
-
-                let previous_index = index;
-                index = Board::get_index(y as i32, x as i32).unwrap();
+    fn draw_paused(&self) {
+        let (title, lines) = match self.new_game {
+            Some(_) => ("CONTINUE?", ["press Enter to continue".to_owned(), "press N for a new game".to_owned()]),
+            None => {
+                let resume = self.settings.keys.pause.first().map_or("pause".to_owned(), |key| format!("{:?}", key));
+                ("PAUSED", [format!("press {} to resume", resume), "press Q to save and quit".to_owned()])
+            },
+        };
 
-                if self.board.grid[index as usize].occupied {
-                    self.board.grid[index as usize].occupied = false;
-                    self.board.grid[previous_index as usize].occupied = true;
-                    self.board.grid[previous_index as usize].tetromino_type = self.board.grid[index as usize].tetromino_type;
-                }
-            }
+        let width = self.game().board().width() as f32 * self.block_size;
+        let height = self.game().board().height() as f32 * self.block_size;
+        draw_rectangle(self.x_start, self.y_start, width, height, Color::new(0.0, 0.0, 0.0, 0.75));
 
+        let x = self.x_start + 20.0;
+        let y = self.y_start + height / 3.0;
+        draw_text(title, x, y, 40.0, WHITE);
+        for (i, line) in lines.iter().enumerate() {
+            draw_text(line, x, y + 50.0 + i as f32 * 28.0, 24.0, YELLOW);
         }
     }
 
-    fn reset_due_to_game_over(&mut self) {
-        self.board = Board::new();
-    }
-
-    // ----
-
-    fn draw(&mut self) {
-        let mut block_index: u8 = 0;
-        for block in self.board.grid.iter() {
-            let mut color = WHITE;
-            if block.occupied {
-                color =  self.tetromino_types[block.tetromino_type as usize].color;
-            }
-            if self.board.is_point_inside_block(mouse_position(), block_index) {
-                color = GREEN;
-            }
+    fn draw_playback_bar(&self, playback: &Playback) {
+        let y = self.settings.window_height as f32 - 40.0;
+        let mut status = format!(
+            "REPLAY  {}x  {} / {}",
+            PLAYBACK_SPEEDS[self.playback_speed],
+            format_time(playback.game().time()),
+            format_time(playback.replay().duration()),
+        );
+        if self.paused {
+            status.push_str("  PAUSED");
+        }
+        if playback.is_finished() {
+            // a replay which ends somewhere else than it did when recorded has desynced
+            let desynced = self.expected_summary.is_some_and(|expected| expected != ReplaySummary::of(playback.game()));
+            status.push_str(if desynced { "  DESYNC" } else { "  END" });
+        }
+        draw_text(&status, 10.0, y, 24.0, YELLOW);
 
-            let (x, y) = self.board.get_block_position_from_row_col(block.row, block.col);
-            draw_rectangle(x, y, BLOCK_SIZE as f32, BLOCK_SIZE as f32, color);
+        // how far through it is
+        let progress = playback.frame() as f32 / playback.replay().frames().len().max(1) as f32;
+        let width = self.settings.window_width as f32 - 20.0;
+        draw_rectangle(10.0, y + 8.0, width, 4.0, DARKGRAY);
+        draw_rectangle(10.0, y + 8.0, width * progress, 4.0, YELLOW);
+    }
 
-            block_index += 1;
+    fn draw_game_over(&self, reason: &str) {
+        let width = self.game().board().width() as f32 * self.block_size;
+        let height = self.game().board().height() as f32 * self.block_size;
+        draw_rectangle(self.x_start, self.y_start, width, height, Color::new(0.0, 0.0, 0.0, 0.75));
+
+        let x = self.x_start + 20.0;
+        let mut y = self.y_start + height / 3.0;
+        draw_text("GAME OVER", x, y, 40.0, RED);
+        y += 30.0;
+        draw_text(reason, x, y, 20.0, GRAY);
+
+        let scoring = self.game().scoring();
+        let stats = [
+            ("SCORE", scoring.score().to_string()),
+            ("LEVEL", scoring.level().to_string()),
+            ("LINES", scoring.lines().to_string()),
+            ("TIME", format_time(self.game().time())),
+        ];
+        for (label, value) in stats.iter() {
+            y += 36.0;
+            draw_text(label, x, y, 20.0, GRAY);
+            draw_text(value, x + 100.0, y, 28.0, WHITE);
         }
 
-        for &index in self.current_shape.positions.iter() {
-            // board.grid[index as usize].occupied = true;
-            let (x, y) = self.board.get_block_position_from_index(index);
-            let color =  self.tetromino_types[self.current_shape.tetromino_type as usize].color;
-            draw_rectangle(x, y, BLOCK_SIZE as f32, BLOCK_SIZE as f32, color);
+        if self.playback.is_none() {
+            draw_text("press R to restart", x, y + 60.0, 24.0, YELLOW);
+            draw_text("press P to watch the replay", x, y + 88.0, 24.0, YELLOW);
+            draw_text("press S to save it", x, y + 116.0, 24.0, YELLOW);
+            draw_text(&self.message, x, y + 144.0, 20.0, GRAY);
         }
     }
-}
\ No newline at end of file
+}
+
+fn format_time(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
@@ -0,0 +1,387 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::{RowMask, MAX_BOARD_WIDTH};
+use crate::config::MIN_BOARD_SIZE;
+
+// ----
+
+/// One rotation state of a piece: a square grid, `true` where there's a block.
+pub type Pattern = Vec<Vec<bool>>;
+
+/// Offsets to try when a rotation doesn't fit as-is, one list per rotation state
+/// being rotated out of. Written as (x, y) with y pointing up, the way kick tables
+/// are usually listed.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct KickTable {
+    pub clockwise: Vec<Vec<(i32, i32)>>,
+    pub counter_clockwise: Vec<Vec<(i32, i32)>>,
+    /// A kick which makes any T-spin it ends in a full one rather than a mini, like SRS's TST kick.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_t_spin_kick: Option<usize>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PieceDefinition {
+    pub name: String,
+    pub color: [u8; 3], // rgb
+    pub rotations: Vec<Pattern>, // clockwise order, all the same size
+    pub spawn_offset: (i32, i32), // (row, col), from centered just above the visible field
+    pub kicks: Option<String>, // key into the set's kick tables, `None` only rotates in place
+    pub t_spin: bool, // whether locking it after a rotation is checked for T-spins
+}
+
+/// Every piece a game can deal, what they look like in every orientation and how
+/// they kick. The built-in sets live in `pieces/`, see `RotationSystemKind`; others
+/// can be loaded from TOML files in the same format.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "PieceSetFile", into = "PieceSetFile")]
+pub struct PieceSet {
+    pub name: String,
+    pieces: Vec<PieceDefinition>,
+    kick_tables: BTreeMap<String, KickTable>,
+    masks: Vec<Vec<Vec<RowMask>>>, // every pattern as row masks, by piece then rotation state
+}
+
+impl PieceSet {
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|error| error.to_string())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|error| format!("couldn't read {}: {}", path.display(), error))?;
+        Self::from_toml(&text).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    /// How many pieces there are, which is also how many types a randomizer picks from.
+    pub fn len(&self) -> u8 {
+        self.pieces.len() as u8
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+
+    pub fn pieces(&self) -> &[PieceDefinition] {
+        &self.pieces
+    }
+
+    /// The type of the piece called `name`, if there is one.
+    pub fn find(&self, name: &str) -> Option<u8> {
+        self.pieces.iter().position(|piece| piece.name == name).map(|tetromino_type| tetromino_type as u8)
+    }
+
+    pub fn piece(&self, tetromino_type: u8) -> &PieceDefinition {
+        &self.pieces[tetromino_type as usize]
+    }
+
+    pub fn pattern(&self, tetromino_type: u8, rotation_index: u8) -> &Pattern {
+        &self.piece(tetromino_type).rotations[rotation_index as usize]
+    }
+
+    /// A pattern as one mask per row, bit `col` set where there's a block, for `Board::collides`.
+    pub fn masks(&self, tetromino_type: u8, rotation_index: u8) -> &[RowMask] {
+        &self.masks[tetromino_type as usize][rotation_index as usize]
+    }
+
+    /// How many rotation states a piece has, one to four.
+    pub fn rotation_count(&self, tetromino_type: u8) -> u8 {
+        self.piece(tetromino_type).rotations.len() as u8
+    }
+
+    /// Where a piece spawns on a board `width` wide with `hidden_rows` above the visible
+    /// field, as the (row, col) of its pattern's top left: in its first rotation state,
+    /// centered, with its lowest block just above the visible field, then moved by its
+    /// `spawn_offset`.
+    pub fn spawn_position(&self, tetromino_type: u8, width: usize, hidden_rows: usize) -> (i32, i32) {
+        let piece = self.piece(tetromino_type);
+        let pattern = &piece.rotations[0];
+
+        let lowest_pattern_row = pattern.iter().rposition(|row| row.contains(&true)).unwrap() as i32;
+        let row = hidden_rows as i32 - 1 - lowest_pattern_row + piece.spawn_offset.0;
+
+        // odd gaps leave the piece a column left of center, as the Guideline has it
+        let (left_col, right_col) = occupied_cols(pattern);
+        let piece_width = right_col - left_col + 1;
+        let col = (width as i32 - piece_width).div_euclid(2) - left_col + piece.spawn_offset.1;
+
+        (row, col)
+    }
+
+    /// The narrowest and shortest board every piece has room to spawn on, never less
+    /// than `MIN_BOARD_SIZE`. Anything bigger has room too.
+    pub fn min_board_size(&self) -> (usize, usize) {
+        // there's always one, see `try_from`
+        let width = (MIN_BOARD_SIZE..=MAX_BOARD_WIDTH).find(|&width| self.fits_spawn_width(width)).unwrap_or(MAX_BOARD_WIDTH);
+
+        // boards have as many hidden rows as visible ones, and the top and bottom blocks both need to be in them
+        let height = self.pieces.iter().map(|piece| {
+            let pattern = &piece.rotations[0];
+            let top = pattern.iter().position(|row| row.contains(&true)).unwrap() as i32;
+            let bottom = pattern.iter().rposition(|row| row.contains(&true)).unwrap() as i32;
+            (bottom - top + 1 - piece.spawn_offset.0).max(piece.spawn_offset.0).max(0) as usize
+        });
+
+        (width, height.fold(MIN_BOARD_SIZE, usize::max))
+    }
+
+    fn fits_spawn_width(&self, width: usize) -> bool {
+        (0..self.len()).all(|tetromino_type| self.spawns_between_walls(tetromino_type, width))
+    }
+
+    // whether a piece's spawn position is between the walls of a board this wide
+    fn spawns_between_walls(&self, tetromino_type: u8, width: usize) -> bool {
+        let (left_col, right_col) = occupied_cols(self.pattern(tetromino_type, 0));
+        let (_, col) = self.spawn_position(tetromino_type, width, 0);
+        col + left_col >= 0 && col + right_col < width as i32
+    }
+
+    /// The kick table a piece uses, if it kicks at all.
+    pub fn kick_table(&self, tetromino_type: u8) -> Option<&KickTable> {
+        self.piece(tetromino_type).kicks.as_ref().map(|name| &self.kick_tables[name])
+    }
+
+    /// The offsets a piece's kick table lists for rotating out of rotation state `from`,
+    /// or just rotating in place without one.
+    pub fn table_kicks(&self, tetromino_type: u8, from: u8, clockwise: bool) -> Vec<(i32, i32)> {
+        let table = match self.kick_table(tetromino_type) {
+            Some(table) => table,
+            None => return vec![(0, 0)],
+        };
+
+        let lists = if clockwise { &table.clockwise } else { &table.counter_clockwise };
+        match lists.get(from as usize) {
+            Some(kicks) => kicks.clone(),
+            None => vec![(0, 0)],
+        }
+    }
+}
+
+// ----
+
+// what's actually written in a piece file, checked and turned into a `PieceSet`
+
+#[derive(Clone, Serialize, Deserialize)]
+struct PieceSetFile {
+    #[serde(default)]
+    name: String,
+    pieces: Vec<PieceFile>,
+    #[serde(default)]
+    kicks: BTreeMap<String, KickTable>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct PieceFile {
+    name: String,
+    color: [u8; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kicks: Option<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    t_spin: bool,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    spawn_offset: (i32, i32),
+    rotations: Vec<Vec<String>>, // rows of '#' for a block and '.' for empty
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+fn is_zero(value: &(i32, i32)) -> bool {
+    *value == (0, 0)
+}
+
+impl TryFrom<PieceSetFile> for PieceSet {
+    type Error = String;
+
+    fn try_from(file: PieceSetFile) -> Result<Self, Self::Error> {
+        if file.pieces.is_empty() || file.pieces.len() > u8::MAX as usize {
+            return Err(format!("a piece set needs 1 to {} pieces, found {}", u8::MAX, file.pieces.len()));
+        }
+
+        for (name, table) in file.kicks.iter() {
+            if table.clockwise.iter().chain(table.counter_clockwise.iter()).any(|kicks| kicks.is_empty()) {
+                return Err(format!("kick table '{}' has an empty list, which would make rotating impossible", name));
+            }
+        }
+
+        let mut pieces = Vec::new();
+        for piece in file.pieces {
+            if let Some(kicks) = &piece.kicks {
+                if !file.kicks.contains_key(kicks) {
+                    return Err(format!("piece {} uses kick table '{}', which isn't defined", piece.name, kicks));
+                }
+            }
+
+            pieces.push(PieceDefinition {
+                rotations: parse_rotations(&piece.name, &piece.rotations)?,
+                name: piece.name,
+                color: piece.color,
+                spawn_offset: piece.spawn_offset,
+                kicks: piece.kicks,
+                t_spin: piece.t_spin,
+            });
+        }
+
+        let masks = pieces.iter().map(|piece| piece.rotations.iter().map(to_masks).collect()).collect();
+        let piece_set = Self {
+            name: file.name,
+            pieces,
+            kick_tables: file.kicks,
+            masks,
+        };
+
+        // how big the board is comes later, but there has to be one which fits
+        if let Some(tetromino_type) = (0..piece_set.len()).find(|&tetromino_type| !piece_set.spawns_between_walls(tetromino_type, MAX_BOARD_WIDTH)) {
+            let name = &piece_set.piece(tetromino_type).name;
+            return Err(format!("piece {} spawns past the walls of even a {} column board, check its spawn_offset", name, MAX_BOARD_WIDTH));
+        }
+
+        Ok(piece_set)
+    }
+}
+
+fn parse_rotations(name: &str, rotations: &[Vec<String>]) -> Result<Vec<Pattern>, String> {
+    if rotations.is_empty() || rotations.len() > 4 {
+        return Err(format!("piece {} needs 1 to 4 rotation states, found {}", name, rotations.len()));
+    }
+
+    let size = rotations[0].len();
+    if size > MAX_BOARD_WIDTH {
+        return Err(format!("piece {} is {} cells across, at most {} fit on a board", name, size, MAX_BOARD_WIDTH));
+    }
+
+    let mut block_count = None;
+    let mut patterns = Vec::new();
+
+    for (state, rows) in rotations.iter().enumerate() {
+        if rows.len() != size || rows.iter().any(|row| row.chars().count() != size) {
+            return Err(format!("piece {} rotation state {} isn't a {}x{} square like the first", name, state, size, size));
+        }
+
+        let mut pattern = Pattern::new();
+        for row in rows {
+            let cells = row.chars().map(|cell| match cell {
+                '#' => Ok(true),
+                '.' => Ok(false),
+                _ => Err(format!("piece {} has '{}' in a rotation state, expected '#' or '.'", name, cell)),
+            });
+            pattern.push(cells.collect::<Result<Vec<bool>, String>>()?);
+        }
+
+        let count = pattern.iter().flatten().filter(|&&cell| cell).count();
+        if count == 0 || block_count.is_some_and(|block_count| block_count != count) {
+            return Err(format!("piece {} rotation state {} has {} blocks, every state needs the same number and at least one", name, state, count));
+        }
+        block_count = Some(count);
+
+        patterns.push(pattern);
+    }
+
+    Ok(patterns)
+}
+
+// the leftmost and rightmost columns with a block in them
+fn occupied_cols(pattern: &Pattern) -> (i32, i32) {
+    let occupied = |col: &usize| pattern.iter().any(|row| row[*col]);
+    let left = (0..pattern.len()).find(occupied).unwrap();
+    let right = (0..pattern.len()).rev().find(occupied).unwrap();
+    (left as i32, right as i32)
+}
+
+fn to_masks(pattern: &Pattern) -> Vec<RowMask> {
+    pattern.iter().map(|row| {
+        row.iter().enumerate().filter(|(_, &cell)| cell).fold(0, |mask, (col, _)| mask | 1 << col)
+    }).collect()
+}
+
+impl From<PieceSet> for PieceSetFile {
+    fn from(piece_set: PieceSet) -> Self {
+        let pieces = piece_set.pieces.into_iter().map(|piece| PieceFile {
+            rotations: piece.rotations.iter().map(|pattern| {
+                pattern.iter().map(|row| row.iter().map(|&cell| if cell { '#' } else { '.' }).collect()).collect()
+            }).collect(),
+            name: piece.name,
+            color: piece.color,
+            kicks: piece.kicks,
+            t_spin: piece.t_spin,
+            spawn_offset: piece.spawn_offset,
+        }).collect();
+
+        Self {
+            name: piece_set.name,
+            pieces,
+            kicks: piece_set.kick_tables,
+        }
+    }
+}
+
+// ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotation::RotationSystemKind;
+
+    #[test]
+    fn tetrominoes_fit_the_smallest_board() {
+        assert_eq!(RotationSystemKind::Srs.piece_set().min_board_size(), (MIN_BOARD_SIZE, MIN_BOARD_SIZE));
+    }
+
+    #[test]
+    fn pentominoes_need_five_columns() {
+        // every pentomino spawns lying down, so none of them is more than 4 tall
+        assert_eq!(RotationSystemKind::Pentomino.piece_set().min_board_size(), (5, MIN_BOARD_SIZE));
+    }
+
+    #[test]
+    fn spawn_offsets_change_the_room_needed() {
+        let pieces = PieceSet::from_toml(concat!(
+            "[[pieces]]\n",
+            "name = 'wide'\n",
+            "color = [0, 0, 0]\n",
+            "spawn_offset = [-2, 3]\n",
+            "rotations = [['......', '######', '......', '......', '......', '......']]\n",
+        )).unwrap();
+
+        // 6 across, shifted 3 right of center, and 1 tall, raised 2 rows
+        assert_eq!(pieces.min_board_size(), (11, 4));
+    }
+
+    #[test]
+    fn bad_piece_files_are_rejected() {
+        let missing_table = concat!(
+            "[[pieces]]\n",
+            "name = 'I'\n",
+            "color = [0, 0, 0]\n",
+            "kicks = 'nowhere'\n",
+            "rotations = [['#']]\n",
+        );
+        let uneven_states = concat!(
+            "[[pieces]]\n",
+            "name = 'I'\n",
+            "color = [0, 0, 0]\n",
+            "rotations = [['##', '..'], ['#.', '..']]\n",
+        );
+
+        assert!(PieceSet::from_toml(missing_table).unwrap_err().contains("kick table 'nowhere', which isn't defined"));
+        assert!(PieceSet::from_toml(uneven_states).unwrap_err().contains("every state needs the same number"));
+    }
+
+    #[test]
+    fn pieces_which_cant_spawn_on_any_board_are_rejected() {
+        let error = PieceSet::from_toml(concat!(
+            "[[pieces]]\n",
+            "name = 'lost'\n",
+            "color = [0, 0, 0]\n",
+            "spawn_offset = [0, 40]\n",
+            "rotations = [['#']]\n",
+        )).unwrap_err();
+
+        assert!(error.contains("piece lost spawns past the walls"), "{}", error);
+    }
+}
@@ -0,0 +1,425 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::piece_set::PieceSet;
+use crate::rng::Rng;
+
+// ----
+
+const HISTORY_LENGTH: usize = 4; // pieces the TGM randomizers remember
+
+// ----
+
+/// Decides which piece comes next.
+pub trait Randomizer {
+    /// Type of the next piece, below the number of pieces it was built for.
+    fn next(&mut self, rng: &mut Rng) -> u8;
+
+    /// Whatever it remembers between pieces, for save files. Nothing by default.
+    fn state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Picks up from a `state` saved by a randomizer built the same way.
+    fn restore(&mut self, state: &[u8]) -> Result<(), String> {
+        if !state.is_empty() {
+            return Err("this randomizer has no state to restore".to_owned());
+        }
+
+        Ok(())
+    }
+}
+
+// ----
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum RandomizerKind {
+    #[default]
+    Bag7,
+    Bag14,
+    Tgm1,
+    Tgm2,
+    Nes,
+    Random,
+}
+
+impl RandomizerKind {
+    pub fn build(self, pieces: &PieceSet) -> Box<dyn Randomizer> {
+        let piece_count = pieces.len();
+
+        // the TGM rules name tetrominoes, which other sets may not have
+        let find_all = |names: &[&str]| names.iter().map(|name| pieces.find(name)).collect::<Option<Vec<u8>>>().unwrap_or_default();
+        let never_first = ["O", "S", "Z"].iter().filter_map(|name| pieces.find(name)).collect();
+
+        match self {
+            RandomizerKind::Bag7 => Box::new(Bag::new(piece_count, 1)),
+            RandomizerKind::Bag14 => Box::new(Bag::new(piece_count, 2)),
+            RandomizerKind::Tgm1 => Box::new(History::new(piece_count, 4, find_all(&["Z", "Z", "Z", "Z"]), never_first)),
+            RandomizerKind::Tgm2 => Box::new(History::new(piece_count, 6, find_all(&["Z", "S", "S", "Z"]), never_first)),
+            RandomizerKind::Nes => Box::new(Nes::new(piece_count)),
+            RandomizerKind::Random => Box::new(PureRandom::new(piece_count)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RandomizerKind::Bag7 => "7-bag",
+            RandomizerKind::Bag14 => "14-bag",
+            RandomizerKind::Tgm1 => "tgm1",
+            RandomizerKind::Tgm2 => "tgm2",
+            RandomizerKind::Nes => "nes",
+            RandomizerKind::Random => "random",
+        }
+    }
+}
+
+impl FromStr for RandomizerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "7-bag" | "bag" | "bag7" => Ok(RandomizerKind::Bag7),
+            "14-bag" | "bag14" => Ok(RandomizerKind::Bag14),
+            "tgm1" | "tgm" => Ok(RandomizerKind::Tgm1),
+            "tgm2" | "tap" => Ok(RandomizerKind::Tgm2),
+            "nes" => Ok(RandomizerKind::Nes),
+            "random" => Ok(RandomizerKind::Random),
+            _ => Err(format!("unknown randomizer '{}', expected one of 7-bag, 14-bag, tgm1, tgm2, nes, random", s)),
+        }
+    }
+}
+
+// written by name in config and replay files
+
+impl TryFrom<String> for RandomizerKind {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<RandomizerKind> for String {
+    fn from(kind: RandomizerKind) -> Self {
+        kind.name().to_owned()
+    }
+}
+
+// ----
+
+/// Every piece `copies` times in a shuffled bag, dealt out before refilling.
+pub struct Bag {
+    piece_count: u8,
+    copies: u8,
+    bag: Vec<u8>,
+}
+
+impl Bag {
+    pub fn new(piece_count: u8, copies: u8) -> Self {
+        Self {
+            piece_count,
+            copies,
+            bag: Vec::new(),
+        }
+    }
+}
+
+impl Randomizer for Bag {
+    fn next(&mut self, rng: &mut Rng) -> u8 {
+        if self.bag.is_empty() {
+            for _ in 0..self.copies {
+                self.bag.extend(0..self.piece_count);
+            }
+            rng.shuffle(&mut self.bag);
+        }
+
+        self.bag.pop().unwrap()
+    }
+
+    fn state(&self) -> Vec<u8> {
+        self.bag.clone()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), String> {
+        if state.len() > self.piece_count as usize * self.copies as usize || state.iter().any(|&piece| piece >= self.piece_count) {
+            return Err("the saved bag doesn't fit this randomizer".to_owned());
+        }
+
+        self.bag = state.to_vec();
+        Ok(())
+    }
+}
+
+// ----
+
+/// TGM style: reroll up to `rolls` times while the piece is among the last four
+/// dealt, starting from a made up `history`. The first piece is never one of
+/// `never_first` (S, Z and O in TGM).
+pub struct History {
+    piece_count: u8,
+    rolls: u8,
+    history: VecDeque<u8>, // oldest first, up to HISTORY_LENGTH
+    never_first: Vec<u8>,
+    first: bool,
+}
+
+impl History {
+    pub fn new(piece_count: u8, rolls: u8, history: Vec<u8>, never_first: Vec<u8>) -> Self {
+        Self {
+            piece_count,
+            rolls,
+            history: history.into_iter().take(HISTORY_LENGTH).collect(),
+            never_first,
+            first: true,
+        }
+    }
+}
+
+impl Randomizer for History {
+    fn next(&mut self, rng: &mut Rng) -> u8 {
+        let mut piece = rng.below(self.piece_count as u32) as u8;
+
+        if self.first {
+            self.first = false;
+            while self.never_first.contains(&piece) && self.never_first.len() < self.piece_count as usize {
+                piece = rng.below(self.piece_count as u32) as u8;
+            }
+        }
+        else {
+            for _ in 1..self.rolls {
+                if !self.history.contains(&piece) {
+                    break;
+                }
+                piece = rng.below(self.piece_count as u32) as u8;
+            }
+        }
+
+        self.history.push_back(piece);
+        if self.history.len() > HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+
+        piece
+    }
+
+    // the history, oldest first, then whether the first piece is still to come
+    fn state(&self) -> Vec<u8> {
+        self.history.iter().copied().chain(std::iter::once(self.first as u8)).collect()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), String> {
+        match state.split_last() {
+            Some((&first, history)) if history.len() <= HISTORY_LENGTH && history.iter().all(|&piece| piece < self.piece_count) && first <= 1 => {
+                self.history = history.iter().copied().collect();
+                self.first = first == 1;
+                Ok(())
+            },
+            _ => Err("the saved history doesn't fit this randomizer".to_owned()),
+        }
+    }
+}
+
+// ----
+
+/// NES style: roll one extra "dummy" value, and reroll once if that or a repeat
+/// of the previous piece comes up.
+pub struct Nes {
+    piece_count: u8,
+    previous: Option<u8>,
+}
+
+impl Nes {
+    pub fn new(piece_count: u8) -> Self {
+        Self {
+            piece_count,
+            previous: None,
+        }
+    }
+}
+
+impl Randomizer for Nes {
+    fn next(&mut self, rng: &mut Rng) -> u8 {
+        let mut piece = rng.below(self.piece_count as u32 + 1) as u8;
+
+        if piece == self.piece_count || Some(piece) == self.previous {
+            piece = rng.below(self.piece_count as u32) as u8;
+        }
+
+        self.previous = Some(piece);
+
+        piece
+    }
+
+    fn state(&self) -> Vec<u8> {
+        self.previous.into_iter().collect()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), String> {
+        match state {
+            [] => self.previous = None,
+            [piece] if *piece < self.piece_count => self.previous = Some(*piece),
+            _ => return Err("the saved previous piece doesn't fit this randomizer".to_owned()),
+        }
+
+        Ok(())
+    }
+}
+
+// ----
+
+/// Every piece equally likely, every time.
+pub struct PureRandom {
+    piece_count: u8,
+}
+
+impl PureRandom {
+    pub fn new(piece_count: u8) -> Self {
+        Self {
+            piece_count,
+        }
+    }
+}
+
+impl Randomizer for PureRandom {
+    fn next(&mut self, rng: &mut Rng) -> u8 {
+        rng.below(self.piece_count as u32) as u8
+    }
+}
+
+// ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotation::RotationSystemKind;
+
+    const KINDS: [RandomizerKind; 6] = [
+        RandomizerKind::Bag7,
+        RandomizerKind::Bag14,
+        RandomizerKind::Tgm1,
+        RandomizerKind::Tgm2,
+        RandomizerKind::Nes,
+        RandomizerKind::Random,
+    ];
+
+    fn tetrominoes() -> PieceSet {
+        RotationSystemKind::Srs.piece_set()
+    }
+
+    fn deal(randomizer: &mut dyn Randomizer, rng: &mut Rng, count: usize) -> Vec<u8> {
+        (0..count).map(|_| randomizer.next(rng)).collect()
+    }
+
+    #[test]
+    fn seeds_deal_the_same_pieces_every_time() {
+        // replays depend on these never changing
+        let expected = [
+            "ZIJSTOLJSTLIOZ",
+            "OLOSIIJZZTLJTS",
+            "JLITZSJOJISTIJ",
+            "JLITZSJOITSJZS",
+            "ZJLITJTLSJOSSJ",
+            "ZJJLITJZLSJOOO",
+        ];
+
+        for (kind, expected) in KINDS.iter().zip(expected) {
+            let dealt = deal(&mut *kind.build(&tetrominoes()), &mut Rng::new(42), 14);
+            let names: String = dealt.iter().map(|&piece| "IJLOSZT".as_bytes()[piece as usize] as char).collect();
+            assert_eq!(names, expected, "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn pieces_stay_in_the_set() {
+        for kind in KINDS {
+            let dealt = deal(&mut *kind.build(&tetrominoes()), &mut Rng::new(0), 500);
+            assert!(dealt.iter().all(|&piece| piece < 7), "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn bags_deal_every_piece_once_per_bag() {
+        for (kind, copies) in [(RandomizerKind::Bag7, 1), (RandomizerKind::Bag14, 2)] {
+            let bag_size = 7 * copies;
+            for seed in 0..20 {
+                let dealt = deal(&mut *kind.build(&tetrominoes()), &mut Rng::new(seed), bag_size * 10);
+                for bag in dealt.chunks(bag_size) {
+                    for piece in 0..7 {
+                        let count = bag.iter().filter(|&&dealt| dealt == piece).count();
+                        assert_eq!(count, copies, "{} seed {} dealt {:?}", kind.name(), seed, bag);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tgm_never_starts_with_s_z_or_o() {
+        for kind in [RandomizerKind::Tgm1, RandomizerKind::Tgm2] {
+            for seed in 0..100 {
+                let first = kind.build(&tetrominoes()).next(&mut Rng::new(seed));
+                assert!(![3, 4, 5].contains(&first), "{} seed {} started with {}", kind.name(), seed, first);
+            }
+        }
+    }
+
+    #[test]
+    fn tgm_finds_s_z_and_o_by_name() {
+        // the same names as ever, in another order
+        let names = ["S", "T", "Z", "I", "O", "L", "J"];
+        let text: String = names.iter().map(|name| format!("[[pieces]]\nname = '{}'\ncolor = [0, 0, 0]\nrotations = [['#']]\n", name)).collect();
+        let pieces = PieceSet::from_toml(&text).unwrap();
+
+        for kind in [RandomizerKind::Tgm1, RandomizerKind::Tgm2] {
+            for seed in 0..100 {
+                let first = kind.build(&pieces).next(&mut Rng::new(seed));
+                assert!(!["O", "S", "Z"].contains(&names[first as usize]), "{} seed {} started with {}", kind.name(), seed, names[first as usize]);
+            }
+        }
+    }
+
+    #[test]
+    fn tgm_skips_its_rules_for_sets_without_s_z_and_o() {
+        let pieces = RotationSystemKind::Pentomino.piece_set();
+        let mut randomizer = RandomizerKind::Tgm1.build(&pieces);
+        assert_eq!(randomizer.state(), vec![1]);
+
+        let mut rng = Rng::new(7);
+        let dealt = deal(&mut *randomizer, &mut rng, 6);
+        assert_eq!(randomizer.state(), dealt[2..].iter().copied().chain(std::iter::once(0)).collect::<Vec<u8>>());
+
+        // a fresh one starts with no history, but still takes a full one back
+        let mut restored = RandomizerKind::Tgm1.build(&pieces);
+        restored.restore(&randomizer.state()).unwrap();
+        assert_eq!(restored.state(), randomizer.state());
+    }
+
+    #[test]
+    fn restored_randomizers_carry_on_the_same() {
+        for kind in KINDS {
+            for dealt_before in [0, 1, 5, 13] {
+                let mut rng = Rng::new(3);
+                let mut randomizer = kind.build(&tetrominoes());
+                deal(&mut *randomizer, &mut rng, dealt_before);
+
+                let mut restored = kind.build(&tetrominoes());
+                restored.restore(&randomizer.state()).unwrap();
+                let mut restored_rng = Rng::new(rng.state());
+
+                assert_eq!(deal(&mut *restored, &mut restored_rng, 30), deal(&mut *randomizer, &mut rng, 30), "{} after {}", kind.name(), dealt_before);
+            }
+        }
+    }
+
+    #[test]
+    fn states_from_other_randomizers_are_rejected() {
+        let pieces = tetrominoes();
+        for kind in KINDS {
+            assert!(kind.build(&pieces).restore(&[pieces.len(); 15]).is_err(), "{}", kind.name());
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use crate::bytes::{Reader, Writer};
+use crate::config::Config;
+use crate::game::{Event, Game};
+use crate::input::Input;
+
+// ----
+
+/// One call to `Game::update`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Frame {
+    pub input: Input,
+    pub dt: f64,
+}
+
+/// Everything needed to play a game out again: how it was set up and every input
+/// it was fed. Games record themselves, see `Game::replay`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Replay {
+    config: Config,
+    seed: u64,
+    frames: Vec<Frame>,
+}
+
+impl Replay {
+    pub fn new(config: Config, seed: u64) -> Self {
+        Self {
+            config,
+            seed,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Seconds of play, which is also what `Game::time` reads at the end.
+    pub fn duration(&self) -> f64 {
+        self.frames.iter().map(|frame| frame.dt).sum()
+    }
+
+    pub(crate) fn record(&mut self, input: Input, dt: f64) {
+        self.frames.push(Frame { input, dt });
+    }
+
+    // seed, config as TOML, then a u32 frame count and each frame's input bits and dt
+    pub(crate) fn write(&self, writer: &mut Writer) -> Result<(), String> {
+        let config = toml::to_string(&self.config).map_err(|error| format!("couldn't write the config: {}", error))?;
+
+        writer.u64(self.seed);
+        writer.string(&config);
+        writer.u32(self.frames.len() as u32);
+        for frame in self.frames.iter() {
+            writer.u8(frame.input.to_bits());
+            writer.f64(frame.dt);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self, String> {
+        let seed = reader.u64()?;
+        let config = toml::from_str(&reader.string()?).map_err(|error| format!("bad config: {}", error))?;
+
+        let mut replay = Self::new(config, seed);
+        for _ in 0..reader.u32()? {
+            let input = Input::from_bits(reader.u8()?);
+            replay.record(input, reader.f64()?);
+        }
+
+        Ok(replay)
+    }
+}
+
+// ----
+
+/// Plays a replay back through a fresh `Game`, which ends up exactly as the
+/// recorded one did. Can go forward in real time, frame by frame, or jump anywhere.
+pub struct Playback {
+    replay: Replay,
+    game: Game,
+    frame: usize, // how many frames have been played
+    clock: f64, // time owed to the next frame, see `advance`
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            game: Game::new(replay.config.clone(), replay.seed),
+            replay,
+            frame: 0,
+            clock: 0.0,
+        }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// The game as it was after `frame` frames.
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame == self.replay.frames.len()
+    }
+
+    /// Plays the next frame, if there is one.
+    pub fn step(&mut self) -> Vec<Event> {
+        match self.replay.frames.get(self.frame) {
+            Some(frame) => {
+                self.frame += 1;
+                self.game.update(frame.input, frame.dt)
+            },
+            None => Vec::new(),
+        }
+    }
+
+    /// Plays as many frames as fit in `dt` seconds of recorded time. Scale `dt` to change the speed.
+    pub fn advance(&mut self, dt: f64) -> Vec<Event> {
+        self.clock += dt;
+
+        let mut events = Vec::new();
+        while let Some(frame) = self.replay.frames.get(self.frame) {
+            if frame.dt > self.clock {
+                break;
+            }
+
+            self.clock -= frame.dt;
+            events.extend(self.step());
+        }
+
+        if self.is_finished() {
+            self.clock = 0.0;
+        }
+
+        events
+    }
+
+    /// Jumps to just after `frame` frames. Going back plays everything again from the start.
+    pub fn seek(&mut self, frame: usize) {
+        let frame = frame.min(self.replay.frames.len());
+        if frame < self.frame {
+            self.game = Game::new(self.replay.config.clone(), self.replay.seed);
+            self.frame = 0;
+        }
+
+        while self.frame < frame {
+            self.step();
+        }
+        self.clock = 0.0;
+    }
+
+    /// Jumps to the first frame at least `time` seconds in.
+    pub fn seek_time(&mut self, time: f64) {
+        let mut elapsed = 0.0;
+        let frame = self.replay.frames.iter().position(|frame| {
+            elapsed += frame.dt;
+            elapsed >= time
+        });
+
+        match frame {
+            Some(frame) if time > 0.0 => self.seek(frame + 1),
+            Some(_) => self.seek(0),
+            None => self.seek(self.replay.frames.len()),
+        }
+    }
+}
+
+// ----
+
+/// Feeds `game` 1/60 s frames with every button but the drops mashed in a fixed
+/// pattern. Frame numbers carrying on from an earlier call carry on the pattern.
+#[cfg(test)]
+pub(crate) fn mash(game: &mut Game, frames: std::ops::Range<u32>) {
+    for frame in frames {
+        game.update(Input::from_bits((frame * 97 % 251) as u8 & 0b0011_1111), 1.0 / 60.0);
+    }
+}
+
+/// A game mashed for `frames` frames, for tests which want one with a history.
+#[cfg(test)]
+pub(crate) fn mashed_game(seed: u64, frames: u32) -> Game {
+    let mut game = Game::new(Config::default(), seed);
+    mash(&mut game, 0..frames);
+    game
+}
+
+// ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::RowMask;
+
+    const FRAME: f64 = 1.0 / 60.0;
+
+    // enough of a game to tell two apart: the stack, the piece in play, the score and the clock
+    fn state(game: &Game) -> (Vec<RowMask>, Vec<usize>, u64, f64) {
+        let rows = (0..game.board().total_rows()).map(|row| game.board().row_mask(row)).collect();
+        (rows, game.current_shape().positions.clone(), game.scoring().score(), game.time())
+    }
+
+    fn stepped(replay: &Replay, frames: usize) -> Playback {
+        let mut playback = Playback::new(replay.clone());
+        for _ in 0..frames {
+            playback.step();
+        }
+
+        playback
+    }
+
+    #[test]
+    fn playing_back_ends_where_the_game_did() {
+        let game = mashed_game(3, 600);
+        let playback = stepped(game.replay(), 600);
+
+        assert!(playback.is_finished());
+        assert_eq!(state(playback.game()), state(&game));
+    }
+
+    #[test]
+    fn seeking_is_the_same_as_stepping() {
+        let replay = mashed_game(3, 600).replay().clone();
+        let mut playback = Playback::new(replay.clone());
+
+        // forwards, backwards to the middle and the start, then past the end
+        for frame in [250, 400, 100, 0, 700] {
+            playback.seek(frame);
+            let expected = stepped(&replay, frame);
+            assert_eq!(playback.frame(), expected.frame());
+            assert_eq!(state(playback.game()), state(expected.game()), "frame {}", frame);
+        }
+    }
+
+    #[test]
+    fn seeking_by_time_finds_the_frame() {
+        let replay = mashed_game(3, 600).replay().clone();
+        let mut playback = Playback::new(replay);
+
+        playback.seek_time(5.01);
+        assert_eq!(playback.frame(), 301);
+
+        playback.seek_time(0.0);
+        assert_eq!(playback.frame(), 0);
+
+        playback.seek_time(60.0);
+        assert_eq!(playback.frame(), 600);
+        assert!(playback.is_finished());
+    }
+
+    #[test]
+    fn advancing_plays_the_frames_which_fit() {
+        let replay = mashed_game(3, 600).replay().clone();
+        let mut playback = Playback::new(replay);
+
+        // a quarter of a frame past half a second, at double speed
+        playback.advance(30.25 * FRAME * 2.0);
+        assert_eq!(playback.frame(), 60);
+
+        // the half frame left over counts towards the next one
+        playback.advance(0.25 * FRAME);
+        assert_eq!(playback.frame(), 60);
+        playback.advance(0.75 * FRAME);
+        assert_eq!(playback.frame(), 61);
+    }
+
+    #[test]
+    fn advancing_stops_at_the_end() {
+        let replay = mashed_game(3, 600).replay().clone();
+        let mut playback = Playback::new(replay);
+
+        playback.advance(60.0);
+        assert!(playback.is_finished());
+        assert_eq!(playback.frame(), 600);
+        assert_eq!(playback.clock, 0.0);
+
+        // nothing left to play
+        assert!(playback.step().is_empty());
+        assert_eq!(playback.frame(), 600);
+    }
+}
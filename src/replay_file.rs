@@ -0,0 +1,167 @@
+// The on-disk replay format, laid out as described in `bytes.rs`:
+//
+//     magic            b"TRSR"
+//     version          u16, REPLAY_FORMAT_VERSION
+//     rotation system  string \
+//     randomizer       string  | the ruleset, also in the config, for telling replays apart at a glance
+//     piece set        string /
+//     seed             u64
+//     config           string, TOML
+//     frame count      u32
+//     frames           u8 input bits (see `Input::to_bits`) and f64 dt each
+//     score            u64 \
+//     lines            u32  |
+//     level            u32  | how the game ended, to check a re-simulation against
+//     time             f64  |
+//     top out          u8   | 0 for none, otherwise `TopOut` in declaration order starting at 1
+//     board hash       u64 /  see `Board::hash`
+//     checksum         u64, FNV-1a of every byte before it
+//
+// Bump the version whenever any of this changes.
+
+use std::path::Path;
+
+use crate::bytes;
+use crate::game::{Game, TopOut};
+use crate::replay::{Playback, Replay};
+
+// ----
+
+const MAGIC: &[u8; 4] = b"TRSR";
+pub const REPLAY_FORMAT_VERSION: u16 = 1;
+
+// ----
+
+/// What a game came to, compared after re-simulating a replay to catch desyncs.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ReplaySummary {
+    pub score: u64,
+    pub lines: u32,
+    pub level: u32,
+    pub time: f64,
+    pub top_out: Option<TopOut>,
+    pub board_hash: u64,
+}
+
+impl ReplaySummary {
+    pub fn of(game: &Game) -> Self {
+        Self {
+            score: game.scoring().score(),
+            lines: game.scoring().lines(),
+            level: game.scoring().level(),
+            time: game.time(),
+            top_out: game.top_out(),
+            board_hash: game.board().hash(),
+        }
+    }
+}
+
+// ----
+
+/// A replay together with how it ended, as saved to and loaded from disk.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ReplayFile {
+    pub replay: Replay,
+    pub summary: ReplaySummary,
+    pub rotation_system: String,
+    pub randomizer: String,
+    pub piece_set: String,
+}
+
+impl ReplayFile {
+    /// Everything `game` has been fed so far, and where it got to.
+    pub fn new(game: &Game) -> Self {
+        let config = game.config();
+
+        Self {
+            replay: game.replay().clone(),
+            summary: ReplaySummary::of(game),
+            rotation_system: config.rotation_system.name().to_owned(),
+            randomizer: config.randomizer.name().to_owned(),
+            piece_set: game.pieces().name.clone(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|error| format!("couldn't read {}: {}", path.display(), error))?;
+        Self::from_bytes(&bytes).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_bytes()?).map_err(|error| format!("couldn't write {}: {}", path.display(), error))
+    }
+
+    /// Plays the whole replay through a fresh game, headlessly. The summary it ends
+    /// with differs from the saved one if the engine no longer plays it the same.
+    pub fn verify(&self) -> ReplaySummary {
+        let mut playback = Playback::new(self.replay.clone());
+        playback.seek(self.replay.frames().len());
+        ReplaySummary::of(playback.game())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut result = Ok(());
+        let bytes = bytes::seal(MAGIC, REPLAY_FORMAT_VERSION, |writer| {
+            writer.string(&self.rotation_system);
+            writer.string(&self.randomizer);
+            writer.string(&self.piece_set);
+            result = self.replay.write(writer);
+
+            let summary = &self.summary;
+            writer.u64(summary.score);
+            writer.u32(summary.lines);
+            writer.u32(summary.level);
+            writer.f64(summary.time);
+            writer.u8(TopOut::to_byte(summary.top_out));
+            writer.u64(summary.board_hash);
+        });
+
+        result.map(|()| bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = bytes::unseal(bytes, MAGIC, REPLAY_FORMAT_VERSION, "replay")?;
+
+        let rotation_system = reader.string()?;
+        let randomizer = reader.string()?;
+        let piece_set = reader.string()?;
+        let replay = Replay::read(&mut reader)?;
+
+        let summary = ReplaySummary {
+            score: reader.u64()?,
+            lines: reader.u32()?,
+            level: reader.u32()?,
+            time: reader.f64()?,
+            top_out: TopOut::from_byte(reader.u8()?)?,
+            board_hash: reader.u64()?,
+        };
+        reader.finish()?;
+
+        Ok(Self {
+            replay,
+            summary,
+            rotation_system,
+            randomizer,
+            piece_set,
+        })
+    }
+}
+
+// ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::mashed_game;
+
+    #[test]
+    fn reads_back_what_was_written() {
+        let file = ReplayFile::new(&mashed_game(17, 600));
+        let read = ReplayFile::from_bytes(&file.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(read, file);
+        assert_eq!(read.verify(), file.summary);
+    }
+}
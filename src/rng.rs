@@ -0,0 +1,83 @@
+/// SplitMix64. Small, fast, and fully described by one `u64`, so a game can be
+/// replayed or saved exactly. Everything random in a `Game` goes through this.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: seed,
+        }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Uniform in `0..n`, without modulo bias.
+    pub fn below(&mut self, n: u32) -> u32 {
+        assert!(n > 0, "Rng::below needs a non-empty range");
+
+        let threshold = n.wrapping_neg() % n;
+        loop {
+            let product = self.next_u32() as u64 * n as u64;
+            if product as u32 >= threshold {
+                return (product >> 32) as u32;
+            }
+        }
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i as u32 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+// ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_reference_splitmix64() {
+        let mut rng = Rng::new(0);
+        assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+        assert_eq!(rng.next_u64(), 0x06C4_5D18_8009_454F);
+    }
+
+    #[test]
+    fn state_picks_up_where_it_left_off() {
+        let mut rng = Rng::new(1234);
+        rng.next_u64();
+
+        let mut resumed = Rng::new(rng.state());
+        assert_eq!((0..10).map(|_| resumed.next_u64()).collect::<Vec<_>>(), (0..10).map(|_| rng.next_u64()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn below_stays_in_range() {
+        let mut rng = Rng::new(99);
+        for n in 1..50 {
+            assert!((0..20).all(|_| rng.below(n) < n));
+        }
+    }
+}
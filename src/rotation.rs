@@ -0,0 +1,195 @@
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, DEFAULT_WIDTH};
+use crate::piece_set::PieceSet;
+use crate::tetromino::Tetromino;
+
+// ----
+
+const ARS_CENTER_COLUMN_PIECES: [&str; 3] = ["L", "J", "T"];
+
+// ----
+
+/// Decides where a piece may be nudged to when a rotation doesn't fit as-is. The
+/// offsets themselves are data in the piece set's kick tables; a rotation system
+/// decides how they're used.
+pub trait RotationSystem {
+    /// Offsets to try, in order, when rotating `tetromino` into rotation state `to`.
+    /// Written as (x, y) with y pointing up, the way kick tables are usually listed.
+    fn kicks(&self, pieces: &PieceSet, board: &Board, tetromino: &Tetromino, to: u8, clockwise: bool) -> Vec<(i32, i32)>;
+}
+
+/// Tries each piece's kick table in order, nothing more. SRS, NES and classic
+/// rotation, and the pentomino and polyomino sets.
+pub struct KickTables;
+
+impl RotationSystem for KickTables {
+    fn kicks(&self, pieces: &PieceSet, _board: &Board, tetromino: &Tetromino, _to: u8, clockwise: bool) -> Vec<(i32, i32)> {
+        pieces.table_kicks(tetromino.tetromino_type, tetromino.rotation_pattern_index, clockwise)
+    }
+}
+
+/// Arika Rotation System, as in the TGM series. Kicks come from the kick tables,
+/// except that L, J and T don't kick when the first blocked cell of the new
+/// orientation (in reading order) is in the center column.
+pub struct Ars;
+
+impl RotationSystem for Ars {
+    fn kicks(&self, pieces: &PieceSet, board: &Board, tetromino: &Tetromino, to: u8, clockwise: bool) -> Vec<(i32, i32)> {
+        let name = &pieces.piece(tetromino.tetromino_type).name;
+        if ARS_CENTER_COLUMN_PIECES.contains(&name.as_str()) && is_blocked_in_center_column(pieces, board, tetromino, to) {
+            return vec![(0, 0)];
+        }
+
+        pieces.table_kicks(tetromino.tetromino_type, tetromino.rotation_pattern_index, clockwise)
+    }
+}
+
+fn is_blocked_in_center_column(pieces: &PieceSet, board: &Board, tetromino: &Tetromino, to: u8) -> bool {
+    let pattern = pieces.pattern(tetromino.tetromino_type, to);
+    let center_col = pattern.len() / 2;
+
+    for (row_index, row) in pattern.iter().enumerate() {
+        for (col_index, &is_occupied) in row.iter().enumerate() {
+            if !is_occupied {
+                continue;
+            }
+
+            if board.is_blocked(tetromino.pattern_top_left_row + row_index as i32, tetromino.pattern_top_left_col + col_index as i32) {
+                return col_index == center_col;
+            }
+        }
+    }
+
+    false
+}
+
+// ----
+
+/// The built-in piece sets, named after the rotation systems they come from, plus
+/// pentomino and mixed polyomino modes.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum RotationSystemKind {
+    #[default]
+    Srs,
+    Ars,
+    Nes,
+    Classic,
+    Pentomino,
+    Polyomino,
+}
+
+impl RotationSystemKind {
+    /// The kick rules, which also apply to a `Config::pieces` set played with this system.
+    pub fn build(self) -> Box<dyn RotationSystem> {
+        match self {
+            RotationSystemKind::Ars => Box::new(Ars),
+            _ => Box::new(KickTables),
+        }
+    }
+
+    pub fn piece_set(self) -> PieceSet {
+        let text = match self {
+            RotationSystemKind::Srs => include_str!("../pieces/srs.toml"),
+            RotationSystemKind::Ars => include_str!("../pieces/ars.toml"),
+            RotationSystemKind::Nes => include_str!("../pieces/nes.toml"),
+            RotationSystemKind::Classic => include_str!("../pieces/classic.toml"),
+            RotationSystemKind::Pentomino => include_str!("../pieces/pentomino.toml"),
+            RotationSystemKind::Polyomino => include_str!("../pieces/polyomino.toml"),
+        };
+
+        PieceSet::from_toml(text).expect("built-in piece sets are valid")
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RotationSystemKind::Srs => "srs",
+            RotationSystemKind::Ars => "ars",
+            RotationSystemKind::Nes => "nes",
+            RotationSystemKind::Classic => "classic",
+            RotationSystemKind::Pentomino => "pentomino",
+            RotationSystemKind::Polyomino => "polyomino",
+        }
+    }
+
+    /// Bigger pieces want a wider field than the usual 10 columns.
+    pub fn default_board_width(self) -> usize {
+        match self {
+            RotationSystemKind::Pentomino | RotationSystemKind::Polyomino => 12,
+            _ => DEFAULT_WIDTH,
+        }
+    }
+}
+
+impl FromStr for RotationSystemKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "srs" => Ok(RotationSystemKind::Srs),
+            "ars" | "tgm" => Ok(RotationSystemKind::Ars),
+            "nes" | "nintendo" => Ok(RotationSystemKind::Nes),
+            "classic" => Ok(RotationSystemKind::Classic),
+            "pentomino" | "pentominoes" => Ok(RotationSystemKind::Pentomino),
+            "polyomino" | "polyominoes" => Ok(RotationSystemKind::Polyomino),
+            _ => Err(format!("unknown rotation system '{}', expected one of srs, ars, nes, classic, pentomino, polyomino", s)),
+        }
+    }
+}
+
+// written by name in config and replay files
+
+impl TryFrom<String> for RotationSystemKind {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<RotationSystemKind> for String {
+    fn from(kind: RotationSystemKind) -> Self {
+        kind.name().to_owned()
+    }
+}
+
+// ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn l_j_t_stay_put_when_the_center_column_blocks_first() {
+        // L lying flat, turning upright: the first cell in its way is under the middle
+        let pieces = RotationSystemKind::Ars.piece_set();
+        let board = Board::from_picture(10, 20, &[
+            "..........",
+            "..........",
+            "....#.....",
+            "..........",
+        ]);
+        let l = Tetromino::at(pieces.find("L").unwrap(), &pieces, &board, 0, 36, 3);
+
+        assert_eq!(Ars.kicks(&pieces, &board, &l, 1, true), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn l_j_t_kick_when_a_side_column_blocks_first() {
+        // same turn, but now the top left corner is what's in the way
+        let pieces = RotationSystemKind::Ars.piece_set();
+        let board = Board::from_picture(10, 20, &[
+            "...#......",
+            "..........",
+            "..........",
+            "..........",
+        ]);
+        let l = Tetromino::at(pieces.find("L").unwrap(), &pieces, &board, 0, 36, 3);
+
+        assert_eq!(Ars.kicks(&pieces, &board, &l, 1, true), vec![(0, 0), (1, 0), (-1, 0)]);
+    }
+}
@@ -0,0 +1,405 @@
+// Tetris Guideline scoring: line clears are worth more at higher levels, drops
+// earn a little per row, and the level goes up every 10 lines. Clearing on
+// consecutive locks builds a combo, difficult clears in a row (four lines or more,
+// and T-spins) are back-to-back, and emptying the board is a perfect clear.
+
+use crate::bytes::{Reader, Writer};
+
+// ----
+
+const LINES_PER_LEVEL: u32 = 10;
+const MAX_GRAVITY_LEVEL: u32 = 20; // the gravity formula stops making sense past here
+
+const SOFT_DROP_POINTS_PER_ROW: u64 = 1;
+const HARD_DROP_POINTS_PER_ROW: u64 = 2;
+const COMBO_POINTS: u64 = 50; // per combo step, per level
+
+// ----
+
+/// Whether a lock counts as a T-spin, see `tspin.rs`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TSpin {
+    None,
+    Mini,
+    Full,
+}
+
+// ----
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ClearType {
+    Single,
+    Double,
+    Triple,
+    Tetris,
+    Pentris, // five lines, which only pentominoes can manage
+    Lines(u8), // six or more, which takes custom pieces at least that tall
+    MiniTSpin,
+    MiniTSpinSingle,
+    MiniTSpinDouble,
+    TSpin,
+    TSpinSingle,
+    TSpinDouble,
+    TSpinTriple,
+}
+
+impl ClearType {
+    /// What a lock clearing `lines` lines amounts to. T-spins count even without lines,
+    /// so this is only `None` for a lock which did neither.
+    pub fn new(lines: u8, t_spin: TSpin) -> Option<Self> {
+        match (t_spin, lines) {
+            (TSpin::None, 0) => None,
+            (TSpin::None, 1) => Some(ClearType::Single),
+            (TSpin::None, 2) => Some(ClearType::Double),
+            (TSpin::None, 3) => Some(ClearType::Triple),
+            (TSpin::Mini, 0) => Some(ClearType::MiniTSpin),
+            (TSpin::Mini, 1) => Some(ClearType::MiniTSpinSingle),
+            (TSpin::Mini, 2) => Some(ClearType::MiniTSpinDouble),
+            (TSpin::Full, 0) => Some(ClearType::TSpin),
+            (TSpin::Full, 1) => Some(ClearType::TSpinSingle),
+            (TSpin::Full, 2) => Some(ClearType::TSpinDouble),
+            (_, 3) => Some(ClearType::TSpinTriple), // only the TST kick gets a T there, which makes it a full T-spin anyway
+            // a custom T-spin piece big enough for more just counts the lines
+            (_, 4) => Some(ClearType::Tetris),
+            (_, 5) => Some(ClearType::Pentris),
+            (_, lines) => Some(ClearType::Lines(lines)),
+        }
+    }
+
+    pub fn lines(self) -> u32 {
+        match self {
+            ClearType::MiniTSpin | ClearType::TSpin => 0,
+            ClearType::Single | ClearType::MiniTSpinSingle | ClearType::TSpinSingle => 1,
+            ClearType::Double | ClearType::MiniTSpinDouble | ClearType::TSpinDouble => 2,
+            ClearType::Triple | ClearType::TSpinTriple => 3,
+            ClearType::Tetris => 4,
+            ClearType::Pentris => 5,
+            ClearType::Lines(lines) => lines as u32,
+        }
+    }
+
+    /// Four lines or more, and T-spins which clear lines. These keep a back-to-back going.
+    pub fn is_difficult(self) -> bool {
+        !matches!(
+            self,
+            ClearType::Single | ClearType::Double | ClearType::Triple | ClearType::MiniTSpin | ClearType::TSpin
+        )
+    }
+
+    // points at level 1
+    fn base_points(self) -> u64 {
+        match self {
+            ClearType::Single => 100,
+            ClearType::Double => 300,
+            ClearType::Triple => 500,
+            ClearType::Tetris => 800,
+            ClearType::Pentris => 1200,
+            ClearType::Lines(lines) => 400 * (lines as u64 - 2), // carrying on from tetris and pentris
+            ClearType::MiniTSpin => 100,
+            ClearType::MiniTSpinSingle => 200,
+            ClearType::MiniTSpinDouble => 400,
+            ClearType::TSpin => 400,
+            ClearType::TSpinSingle => 800,
+            ClearType::TSpinDouble => 1200,
+            ClearType::TSpinTriple => 1600,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ClearType::Single => "SINGLE",
+            ClearType::Double => "DOUBLE",
+            ClearType::Triple => "TRIPLE",
+            ClearType::Tetris => "TETRIS",
+            ClearType::Pentris => "PENTRIS",
+            ClearType::Lines(_) => "MULTI-LINE CLEAR",
+            ClearType::MiniTSpin => "MINI T-SPIN",
+            ClearType::MiniTSpinSingle => "MINI T-SPIN SINGLE",
+            ClearType::MiniTSpinDouble => "MINI T-SPIN DOUBLE",
+            ClearType::TSpin => "T-SPIN",
+            ClearType::TSpinSingle => "T-SPIN SINGLE",
+            ClearType::TSpinDouble => "T-SPIN DOUBLE",
+            ClearType::TSpinTriple => "T-SPIN TRIPLE",
+        }
+    }
+}
+
+// points at level 1 on top of the clear itself
+fn perfect_clear_points(lines: u32, back_to_back: bool) -> u64 {
+    match (lines, back_to_back) {
+        (1, _) => 800,
+        (2, _) => 1200,
+        (3, _) => 1800,
+        (0, _) => 0,
+        // 2000 and 3200 for a tetris, then half that again for every line past it
+        (lines, false) => 1000 * (lines as u64 - 2),
+        (lines, true) => 1600 * (lines as u64 - 2),
+    }
+}
+
+// ----
+
+/// How a single clear was scored.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ClearScore {
+    pub points: u64,
+    pub combo: u32, // 0 for the first clear in a row
+    pub back_to_back: bool,
+    pub perfect_clear: bool,
+}
+
+// ----
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Scoring {
+    score: u64,
+    lines: u32,
+    start_level: u32,
+    combo: Option<u32>, // None until a lock clears lines
+    back_to_back: bool, // whether the last clear with lines was difficult
+}
+
+impl Scoring {
+    pub fn new(start_level: u32) -> Self {
+        Self {
+            score: 0,
+            lines: 0,
+            start_level: start_level.max(1),
+            combo: None,
+            back_to_back: false,
+        }
+    }
+
+    pub fn score(&self) -> u64 {
+        self.score
+    }
+
+    pub fn lines(&self) -> u32 {
+        self.lines
+    }
+
+    pub fn combo(&self) -> Option<u32> {
+        self.combo
+    }
+
+    /// Whether the next difficult clear will be back-to-back.
+    pub fn back_to_back(&self) -> bool {
+        self.back_to_back
+    }
+
+    pub fn level(&self) -> u32 {
+        self.start_level + self.lines / LINES_PER_LEVEL
+    }
+
+    /// Seconds it takes gravity to pull a piece down one row at the current level.
+    pub fn gravity(&self) -> f64 {
+        let level = self.level().min(MAX_GRAVITY_LEVEL) as f64;
+        (0.8 - (level - 1.0) * 0.007).powf(level - 1.0)
+    }
+
+    pub(crate) fn add_soft_drop(&mut self, rows: u32) -> u64 {
+        self.add_points(rows as u64 * SOFT_DROP_POINTS_PER_ROW)
+    }
+
+    pub(crate) fn add_hard_drop(&mut self, rows: u32) -> u64 {
+        self.add_points(rows as u64 * HARD_DROP_POINTS_PER_ROW)
+    }
+
+    /// A lock which cleared nothing ends the combo.
+    pub(crate) fn break_combo(&mut self) {
+        self.combo = None;
+    }
+
+    /// Scores a clear at the level it was made on, then counts its lines towards the next level.
+    pub(crate) fn add_clear(&mut self, clear_type: ClearType, perfect_clear: bool) -> ClearScore {
+        let level = self.level() as u64;
+        let lines = clear_type.lines();
+        let back_to_back = clear_type.is_difficult() && self.back_to_back;
+
+        let mut points = clear_type.base_points() * level;
+        if back_to_back {
+            points = points * 3 / 2;
+        }
+
+        if lines == 0 {
+            // a T-spin without lines neither builds nor breaks back-to-back, but it isn't a combo
+            self.combo = None;
+        }
+        else {
+            let combo = self.combo.map_or(0, |combo| combo + 1);
+            points += COMBO_POINTS * combo as u64 * level;
+            self.combo = Some(combo);
+            self.back_to_back = clear_type.is_difficult();
+        }
+
+        if perfect_clear {
+            points += perfect_clear_points(lines, back_to_back) * level;
+        }
+
+        self.add_points(points);
+        self.lines += lines;
+
+        ClearScore {
+            points,
+            combo: self.combo.unwrap_or(0),
+            back_to_back,
+            perfect_clear,
+        }
+    }
+
+    pub(crate) fn write(&self, writer: &mut Writer) {
+        writer.u64(self.score);
+        writer.u32(self.lines);
+        writer.u32(self.start_level);
+        writer.bool(self.combo.is_some());
+        writer.u32(self.combo.unwrap_or(0));
+        writer.bool(self.back_to_back);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self, String> {
+        let score = reader.u64()?;
+        let lines = reader.u32()?;
+        let start_level = reader.u32()?.max(1);
+        let has_combo = reader.bool()?;
+        let combo = reader.u32()?;
+
+        Ok(Self {
+            score,
+            lines,
+            start_level,
+            combo: if has_combo { Some(combo) } else { None },
+            back_to_back: reader.bool()?,
+        })
+    }
+
+    fn add_points(&mut self, points: u64) -> u64 {
+        self.score += points;
+        points
+    }
+}
+
+// ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clears_scale_with_the_level_they_were_made_on() {
+        let clears = [(ClearType::Single, 300), (ClearType::Double, 900), (ClearType::Triple, 1500), (ClearType::Tetris, 2400)];
+
+        for (clear_type, points) in clears {
+            let mut scoring = Scoring::new(3);
+            assert_eq!(scoring.add_clear(clear_type, false).points, points);
+            assert_eq!(scoring.score(), points);
+            assert_eq!(scoring.lines(), clear_type.lines());
+        }
+    }
+
+    #[test]
+    fn drops_earn_points_per_row() {
+        let mut scoring = Scoring::new(5);
+
+        assert_eq!(scoring.add_soft_drop(7), 7);
+        assert_eq!(scoring.add_hard_drop(7), 14);
+        assert_eq!(scoring.score(), 21);
+    }
+
+    #[test]
+    fn levels_go_up_every_10_lines() {
+        let mut scoring = Scoring::new(1);
+        for _ in 0..9 {
+            scoring.add_clear(ClearType::Single, false);
+        }
+        assert_eq!(scoring.level(), 1);
+
+        scoring.add_clear(ClearType::Double, false);
+        assert_eq!(scoring.level(), 2);
+    }
+
+    #[test]
+    fn gravity_follows_the_guideline_curve() {
+        let at_level = |level| Scoring::new(level).gravity();
+
+        assert_eq!(at_level(1), 1.0);
+        assert!((at_level(2) - 0.793).abs() < 1e-9);
+        assert!((at_level(15) - 0.007_05).abs() < 1e-5);
+        assert_eq!(at_level(30), at_level(20));
+    }
+
+    #[test]
+    fn perfect_clears_stack_on_the_combo() {
+        let mut scoring = Scoring::new(1);
+
+        // 100 for the single, 800 for the perfect clear, 50 a combo step
+        assert_eq!(scoring.add_clear(ClearType::Single, true).points, 900);
+        assert_eq!(scoring.add_clear(ClearType::Single, true).points, 950);
+        assert_eq!(scoring.add_clear(ClearType::Single, true).points, 1000);
+    }
+
+    #[test]
+    fn back_to_back_perfect_clear_tetrises_pay_more() {
+        let mut scoring = Scoring::new(1);
+
+        let first = scoring.add_clear(ClearType::Tetris, true);
+        assert_eq!((first.points, first.back_to_back), (2800, false));
+
+        scoring.break_combo();
+        let second = scoring.add_clear(ClearType::Tetris, true);
+        assert_eq!((second.points, second.back_to_back), (4400, true));
+    }
+
+    #[test]
+    fn t_spins_without_lines_leave_back_to_back_alone() {
+        let mut scoring = Scoring::new(1);
+        scoring.add_clear(ClearType::TSpin, false);
+        assert!(!scoring.back_to_back());
+
+        scoring.add_clear(ClearType::Tetris, false);
+        scoring.add_clear(ClearType::TSpin, false);
+        assert!(scoring.back_to_back());
+        assert!(scoring.add_clear(ClearType::TSpinSingle, false).back_to_back);
+    }
+
+    #[test]
+    fn locks_which_clear_nothing_end_the_combo() {
+        let mut scoring = Scoring::new(1);
+        scoring.add_clear(ClearType::Single, false);
+        assert_eq!(scoring.add_clear(ClearType::Single, false).combo, 1);
+
+        scoring.break_combo();
+        assert_eq!(scoring.combo(), None);
+        assert_eq!(scoring.add_clear(ClearType::Single, false).combo, 0);
+    }
+
+    #[test]
+    fn any_number_of_lines_is_a_clear() {
+        assert_eq!(ClearType::new(0, TSpin::None), None);
+        assert_eq!(ClearType::new(4, TSpin::None), Some(ClearType::Tetris));
+        assert_eq!(ClearType::new(5, TSpin::None), Some(ClearType::Pentris));
+        assert_eq!(ClearType::new(6, TSpin::None), Some(ClearType::Lines(6)));
+        assert_eq!(ClearType::new(4, TSpin::Full), Some(ClearType::Tetris));
+
+        for lines in 1..=64 {
+            assert_eq!(ClearType::new(lines, TSpin::None).unwrap().lines(), lines as u32);
+        }
+    }
+
+    #[test]
+    fn bigger_clears_score_more() {
+        let points = |lines| ClearType::new(lines, TSpin::None).unwrap().base_points();
+        assert!((1..=10).all(|lines| points(lines) < points(lines + 1)));
+        assert!((1..=10).all(|lines| perfect_clear_points(lines, false) < perfect_clear_points(lines + 1, false)));
+    }
+
+    #[test]
+    fn lines_count_towards_the_level() {
+        let mut scoring = Scoring::new(1);
+        let score = scoring.add_clear(ClearType::Lines(6), false);
+        scoring.add_clear(ClearType::Lines(6), false);
+
+        assert_eq!(score.points, 1600);
+        assert_eq!(scoring.lines(), 12);
+        assert_eq!(scoring.level(), 2);
+    }
+}
@@ -0,0 +1,430 @@
+// Settings for the windowed frontend, read from a TOML file in the user's config
+// directory: window size, visuals, key bindings and the rules to play by. Only
+// `main.rs` uses these, they aren't part of the library. See `config.example.toml`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use macroquad::prelude::{is_key_down, is_key_pressed, Color, KeyCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tetris_rs::{problem, Config, PieceSet};
+use toml::value::{Table, Value};
+
+// ----
+
+const MIN_WINDOW_SIZE: i32 = 320;
+
+// keys main.rs reads itself, rather than through the bindings
+pub const GHOST_KEY: KeyCode = KeyCode::G; // any time
+pub const QUIT_KEY: KeyCode = KeyCode::Q; // while paused
+pub const CONTINUE_KEY: KeyCode = KeyCode::Enter; // offered the saved game, and after a game over
+pub const NEW_GAME_KEY: KeyCode = KeyCode::N; // offered the saved game
+pub const RESTART_KEY: KeyCode = KeyCode::R; // after a game over
+pub const WATCH_REPLAY_KEY: KeyCode = KeyCode::P; // after a game over, and to stop watching
+pub const SAVE_REPLAY_KEY: KeyCode = KeyCode::S; // after a game over
+
+// the ones bindings can't use: G works mid-game, Q would quit as soon as it paused,
+// and a key which starts play would still be held down in it. The replay keys only
+// work once the game is over, so S and P are free.
+const RESERVED_KEYS: [(KeyCode, &str); 5] = [
+    (GHOST_KEY, "toggles the ghost"),
+    (QUIT_KEY, "saves and quits while paused"),
+    (CONTINUE_KEY, "continues the saved game and restarts after a game over"),
+    (NEW_GAME_KEY, "starts a new game instead of the saved one"),
+    (RESTART_KEY, "restarts after a game over"),
+];
+
+// every key which can be bound, by the name it goes by in the file
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("Space", KeyCode::Space), ("Apostrophe", KeyCode::Apostrophe), ("Comma", KeyCode::Comma),
+    ("Minus", KeyCode::Minus), ("Period", KeyCode::Period), ("Slash", KeyCode::Slash), ("Key0", KeyCode::Key0),
+    ("Key1", KeyCode::Key1), ("Key2", KeyCode::Key2), ("Key3", KeyCode::Key3), ("Key4", KeyCode::Key4),
+    ("Key5", KeyCode::Key5), ("Key6", KeyCode::Key6), ("Key7", KeyCode::Key7), ("Key8", KeyCode::Key8),
+    ("Key9", KeyCode::Key9), ("Semicolon", KeyCode::Semicolon), ("Equal", KeyCode::Equal), ("A", KeyCode::A),
+    ("B", KeyCode::B), ("C", KeyCode::C), ("D", KeyCode::D), ("E", KeyCode::E), ("F", KeyCode::F), ("G", KeyCode::G),
+    ("H", KeyCode::H), ("I", KeyCode::I), ("J", KeyCode::J), ("K", KeyCode::K), ("L", KeyCode::L), ("M", KeyCode::M),
+    ("N", KeyCode::N), ("O", KeyCode::O), ("P", KeyCode::P), ("Q", KeyCode::Q), ("R", KeyCode::R), ("S", KeyCode::S),
+    ("T", KeyCode::T), ("U", KeyCode::U), ("V", KeyCode::V), ("W", KeyCode::W), ("X", KeyCode::X), ("Y", KeyCode::Y),
+    ("Z", KeyCode::Z), ("LeftBracket", KeyCode::LeftBracket), ("Backslash", KeyCode::Backslash),
+    ("RightBracket", KeyCode::RightBracket), ("GraveAccent", KeyCode::GraveAccent), ("Escape", KeyCode::Escape),
+    ("Enter", KeyCode::Enter), ("Tab", KeyCode::Tab), ("Backspace", KeyCode::Backspace), ("Insert", KeyCode::Insert),
+    ("Delete", KeyCode::Delete), ("Right", KeyCode::Right), ("Left", KeyCode::Left), ("Down", KeyCode::Down),
+    ("Up", KeyCode::Up), ("PageUp", KeyCode::PageUp), ("PageDown", KeyCode::PageDown), ("Home", KeyCode::Home),
+    ("End", KeyCode::End), ("F1", KeyCode::F1), ("F2", KeyCode::F2), ("F3", KeyCode::F3), ("F4", KeyCode::F4),
+    ("F5", KeyCode::F5), ("F6", KeyCode::F6), ("F7", KeyCode::F7), ("F8", KeyCode::F8), ("F9", KeyCode::F9),
+    ("F10", KeyCode::F10), ("F11", KeyCode::F11), ("F12", KeyCode::F12), ("Kp0", KeyCode::Kp0),
+    ("Kp1", KeyCode::Kp1), ("Kp2", KeyCode::Kp2), ("Kp3", KeyCode::Kp3), ("Kp4", KeyCode::Kp4),
+    ("Kp5", KeyCode::Kp5), ("Kp6", KeyCode::Kp6), ("Kp7", KeyCode::Kp7), ("Kp8", KeyCode::Kp8),
+    ("Kp9", KeyCode::Kp9), ("KpDecimal", KeyCode::KpDecimal), ("KpDivide", KeyCode::KpDivide),
+    ("KpMultiply", KeyCode::KpMultiply), ("KpSubtract", KeyCode::KpSubtract), ("KpAdd", KeyCode::KpAdd),
+    ("KpEnter", KeyCode::KpEnter), ("LeftShift", KeyCode::LeftShift), ("LeftControl", KeyCode::LeftControl),
+    ("LeftAlt", KeyCode::LeftAlt), ("RightShift", KeyCode::RightShift), ("RightControl", KeyCode::RightControl),
+    ("RightAlt", KeyCode::RightAlt),
+];
+
+// ----
+
+/// Everything the frontend can be set up with, anything wrong in the file already put back to its default.
+pub struct Settings {
+    pub window_width: i32,
+    pub window_height: i32,
+    pub visuals: Visuals,
+    pub keys: KeyBindings,
+    pub game: Config,
+    pub board_width_given: bool, // whether the file picked a width, rather than leaving it to suit the pieces
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Visuals {
+    pub show_ghost: bool,
+    pub max_block_size: f32, // smaller when the board wouldn't fit otherwise
+    pub shown_buffer_rows: usize, // hidden rows drawn above the field, so pieces can be seen spawning
+    pub ghost_opacity: f32, // 0 to 1
+    pub background: [u8; 3],
+    pub empty_cell: [u8; 3],
+    pub piece_colors: BTreeMap<String, [u8; 3]>, // by piece name, over the piece set's own colors
+}
+
+impl Default for Visuals {
+    fn default() -> Self {
+        Self {
+            show_ghost: true,
+            max_block_size: 32.0,
+            shown_buffer_rows: 2,
+            ghost_opacity: 0.35,
+            background: [26, 26, 26],
+            empty_cell: [255, 255, 255],
+            piece_colors: BTreeMap::new(),
+        }
+    }
+}
+
+/// Which keys do what in a game. Any of a binding's keys will do.
+pub struct KeyBindings {
+    pub left: Vec<KeyCode>,
+    pub right: Vec<KeyCode>,
+    pub soft_drop: Vec<KeyCode>,
+    pub hard_drop: Vec<KeyCode>,
+    pub sonic_drop: Vec<KeyCode>,
+    pub rotate_clockwise: Vec<KeyCode>,
+    pub rotate_counter_clockwise: Vec<KeyCode>,
+    pub hold: Vec<KeyCode>,
+    pub pause: Vec<KeyCode>,
+}
+
+pub fn any_key_down(keys: &[KeyCode]) -> bool {
+    keys.iter().any(|&key| is_key_down(key))
+}
+
+pub fn any_key_pressed(keys: &[KeyCode]) -> bool {
+    keys.iter().any(|&key| is_key_pressed(key))
+}
+
+pub fn to_color([r, g, b]: [u8; 3]) -> Color {
+    Color::from_rgba(r, g, b, 255)
+}
+
+/// Where the settings and the saved game go, e.g. `~/.config/tetris_rs`, or none without a home directory.
+pub fn user_dir() -> Option<PathBuf> {
+    let dir = if cfg!(target_os = "windows") {
+        PathBuf::from(std::env::var_os("APPDATA")?)
+    }
+    else if cfg!(target_os = "macos") {
+        PathBuf::from(std::env::var_os("HOME")?).join("Library").join("Application Support")
+    }
+    else {
+        match std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
+            Some(dir) if dir.is_absolute() => dir,
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        }
+    };
+
+    Some(dir.join("tetris_rs"))
+}
+
+// ----
+
+// what's actually written in the file, checked and turned into `Settings`
+
+#[derive(Default)]
+struct SettingsFile {
+    window: WindowFile,
+    visuals: Visuals,
+    keys: KeysFile,
+    game: Config,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct WindowFile {
+    width: i32,
+    height: i32,
+}
+
+impl Default for WindowFile {
+    fn default() -> Self {
+        Self {
+            width: 640,
+            height: 800,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct KeysFile {
+    left: Vec<String>,
+    right: Vec<String>,
+    soft_drop: Vec<String>,
+    hard_drop: Vec<String>,
+    sonic_drop: Vec<String>,
+    rotate_clockwise: Vec<String>,
+    rotate_counter_clockwise: Vec<String>,
+    hold: Vec<String>,
+    pause: Vec<String>,
+}
+
+impl Default for KeysFile {
+    fn default() -> Self {
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+
+        Self {
+            left: names(&["Left"]),
+            right: names(&["Right"]),
+            soft_drop: names(&["Down"]),
+            hard_drop: names(&["Space"]),
+            sonic_drop: names(&["S"]),
+            rotate_clockwise: names(&["Up", "X"]),
+            rotate_counter_clockwise: names(&["Z", "LeftControl"]),
+            hold: names(&["C", "LeftShift", "RightShift"]),
+            pause: names(&["Escape"]),
+        }
+    }
+}
+
+impl SettingsFile {
+    // section by section and setting by setting, so a mistake only costs the setting
+    // it's in. Also whether the file picked a board width.
+    fn read(file: Table, problems: &mut Vec<String>) -> (Self, bool) {
+        let mut settings = Self::default();
+        let mut board_width_given = false;
+
+        for (name, section) in file {
+            match name.as_str() {
+                "window" => settings.window = read_section(&name, section, problems).0,
+                "visuals" => settings.visuals = read_section(&name, section, problems).0,
+                "keys" => settings.keys = read_section(&name, section, problems).0,
+                "game" => {
+                    let (game, kept) = read_section(&name, section, problems);
+                    settings.game = game;
+                    board_width_given = kept.contains_key("board_width");
+                },
+                _ => problems.push(format!("unknown section [{}], expected window, visuals, keys or game", name)),
+            }
+        }
+
+        (settings, board_width_given)
+    }
+}
+
+// `section`'s settings added one at a time over the defaults, leaving out any which
+// don't fit, then the settings which made it in
+fn read_section<T: DeserializeOwned + Default>(name: &str, section: Value, problems: &mut Vec<String>) -> (T, Table) {
+    let section = match section {
+        Value::Table(section) => section,
+        _ => {
+            problems.push(format!("{} should be a [{}] table, using the defaults for it", name, name));
+            return (T::default(), Table::new());
+        },
+    };
+
+    let mut kept = Table::new();
+    add_settings::<T>(name, &mut kept, &[], section, problems);
+
+    let settings = Value::Table(kept.clone()).try_into().unwrap_or_default();
+    (settings, kept)
+}
+
+// adds `table`'s settings to the table at `path` in `kept` one at a time, as long as
+// `kept` still reads as a `T`. Nested tables like `game.handling` are checked the
+// same way, unless even an empty one wouldn't read, in which case they go or stay whole.
+fn add_settings<T: DeserializeOwned>(name: &str, kept: &mut Table, path: &[String], table: Table, problems: &mut Vec<String>) {
+    for (key, value) in table {
+        let name = format!("{}.{}", name, key);
+
+        let value = match value {
+            Value::Table(nested) => match with_setting::<T>(kept, path, &key, Value::Table(Table::new())) {
+                Ok(tried) => {
+                    *kept = tried;
+                    add_settings::<T>(&name, kept, &[path, &[key]].concat(), nested, problems);
+                    continue;
+                },
+                Err(_) => Value::Table(nested),
+            },
+            value => value,
+        };
+
+        match with_setting::<T>(kept, path, &key, value) {
+            Ok(tried) => *kept = tried,
+            Err(error) => problems.push(format!("{}: {}, ignoring it", name, error)),
+        }
+    }
+}
+
+// `kept` with `value` at `key` in the table at `path`, if that still reads as a `T`
+fn with_setting<T: DeserializeOwned>(kept: &Table, path: &[String], key: &str, value: Value) -> Result<Table, toml::de::Error> {
+    let mut tried = kept.clone();
+    table_at(&mut tried, path).insert(key.to_owned(), value);
+    Value::Table(tried.clone()).try_into::<T>().map(|_| tried)
+}
+
+// the table `path` leads to, which `add_settings` has already put in
+fn table_at<'a>(table: &'a mut Table, path: &[String]) -> &'a mut Table {
+    match path.split_first() {
+        Some((key, rest)) => match table.get_mut(key) {
+            Some(Value::Table(nested)) => table_at(nested, rest),
+            _ => unreachable!("nested tables are added before what's in them"),
+        },
+        None => table,
+    }
+}
+
+// ----
+
+impl Settings {
+    /// Where the settings live unless told otherwise, e.g. `~/.config/tetris_rs/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(user_dir()?.join("config.toml"))
+    }
+
+    /// Reads the settings at `path`, or the default path if there isn't one. Every
+    /// problem comes back as a message, with whatever it affected left at its default.
+    /// No file at the default path is fine, it just means defaults all round.
+    pub fn load(path: Option<&Path>) -> (Self, Vec<String>) {
+        let explicit = path.is_some();
+        let path = match path.map(Path::to_path_buf).or_else(Self::default_path) {
+            Some(path) => path,
+            None => return (Self::from_file(SettingsFile::default(), false, &mut Vec::new()), Vec::new()),
+        };
+
+        let mut problems = Vec::new();
+        let (file, board_width_given) = match std::fs::read_to_string(&path) {
+            Ok(text) => match toml::from_str::<Table>(&text) {
+                Ok(file) => SettingsFile::read(file, &mut problems),
+                Err(error) => {
+                    problems.push(format!("{}, using the default settings", error));
+                    Default::default()
+                },
+            },
+            Err(error) if explicit || error.kind() != std::io::ErrorKind::NotFound => {
+                problems.push(format!("couldn't read it: {}, using the default settings", error));
+                Default::default()
+            },
+            Err(_) => Default::default(),
+        };
+
+        let settings = Self::from_file(file, board_width_given, &mut problems);
+        let problems = problems.into_iter().map(|problem| format!("{}: {}", path.display(), problem)).collect();
+        (settings, problems)
+    }
+
+    // falls back to the default for anything out of range, saying so in `problems`
+    fn from_file(file: SettingsFile, board_width_given: bool, problems: &mut Vec<String>) -> Self {
+        let defaults = SettingsFile::default();
+
+        let mut window = file.window;
+        if window.width < MIN_WINDOW_SIZE {
+            problems.push(problem("window.width", format!("at least {}", MIN_WINDOW_SIZE), window.width, defaults.window.width));
+            window.width = defaults.window.width;
+        }
+        if window.height < MIN_WINDOW_SIZE {
+            problems.push(problem("window.height", format!("at least {}", MIN_WINDOW_SIZE), window.height, defaults.window.height));
+            window.height = defaults.window.height;
+        }
+
+        let mut visuals = file.visuals;
+        if !visuals.max_block_size.is_finite() || visuals.max_block_size < 1.0 {
+            problems.push(problem("visuals.max_block_size", "1 or more", visuals.max_block_size, defaults.visuals.max_block_size));
+            visuals.max_block_size = defaults.visuals.max_block_size;
+        }
+        if !(0.0..=1.0).contains(&visuals.ghost_opacity) {
+            problems.push(problem("visuals.ghost_opacity", "between 0 and 1", visuals.ghost_opacity, defaults.visuals.ghost_opacity));
+            visuals.ghost_opacity = defaults.visuals.ghost_opacity;
+        }
+
+        // every key does one thing, so none are shared between bindings or with the reserved keys
+        let keys = file.keys;
+        let default_keys = defaults.keys;
+        let mut taken: Vec<(KeyCode, String)> = RESERVED_KEYS.iter().map(|&(key, what)| (key, what.to_owned())).collect();
+        let mut bind = |name: &str, names: Vec<String>, default: Vec<String>| {
+            let keys = match parse_keys(&names) {
+                Ok(keys) => keys,
+                Err(key) => {
+                    problems.push(format!("keys.{} has unknown key '{}', using {:?} instead; keys are named like A, Key1, Left, Space, LeftShift or F1", name, key, default));
+                    parse_keys(&default).unwrap()
+                },
+            };
+
+            let is_free = |key: &KeyCode| !taken.iter().any(|(taken_key, _)| taken_key == key);
+            let mut free: Vec<KeyCode> = keys.iter().copied().filter(is_free).collect();
+            for (key, what) in taken.iter().filter(|(taken_key, _)| keys.contains(taken_key)) {
+                problems.push(format!("keys.{} has {:?}, which {}, leaving it out", name, key, what));
+            }
+            if free.is_empty() && !keys.is_empty() {
+                free = parse_keys(&default).unwrap().into_iter().filter(is_free).collect();
+                problems.push(format!("keys.{} has no keys left, using {:?} instead", name, free));
+            }
+
+            taken.extend(free.iter().map(|&key| (key, format!("is already bound to {}", name))));
+            free
+        };
+        let keys = KeyBindings {
+            left: bind("left", keys.left, default_keys.left),
+            right: bind("right", keys.right, default_keys.right),
+            soft_drop: bind("soft_drop", keys.soft_drop, default_keys.soft_drop),
+            hard_drop: bind("hard_drop", keys.hard_drop, default_keys.hard_drop),
+            sonic_drop: bind("sonic_drop", keys.sonic_drop, default_keys.sonic_drop),
+            rotate_clockwise: bind("rotate_clockwise", keys.rotate_clockwise, default_keys.rotate_clockwise),
+            rotate_counter_clockwise: bind("rotate_counter_clockwise", keys.rotate_counter_clockwise, default_keys.rotate_counter_clockwise),
+            hold: bind("hold", keys.hold, default_keys.hold),
+            pause: bind("pause", keys.pause, default_keys.pause),
+        };
+
+        let mut game = file.game;
+        problems.extend(game.sanitize().into_iter().map(|problem| format!("game.{}", problem)));
+
+        Self {
+            window_width: window.width,
+            window_height: window.height,
+            visuals,
+            keys,
+            game,
+            board_width_given,
+        }
+    }
+
+    /// Complains about colors for pieces which aren't in `pieces`, which are probably typos.
+    pub fn check_piece_colors(&self, pieces: &PieceSet) -> Vec<String> {
+        self.visuals.piece_colors.keys()
+            .filter(|name| !pieces.pieces().iter().any(|piece| &piece.name == *name))
+            .map(|name| format!("visuals.piece_colors has a color for '{}', but the {} pieces have no piece by that name", name, pieces.name))
+            .collect()
+    }
+
+    pub fn piece_color(&self, pieces: &PieceSet, tetromino_type: u8) -> Color {
+        let piece = pieces.piece(tetromino_type);
+        to_color(*self.visuals.piece_colors.get(&piece.name).unwrap_or(&piece.color))
+    }
+}
+
+// the first name which isn't a key, if any
+fn parse_keys(names: &[String]) -> Result<Vec<KeyCode>, String> {
+    names.iter().map(|name| {
+        KEY_NAMES.iter()
+            .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+            .map(|&(_, key)| key)
+            .ok_or_else(|| name.clone())
+    }).collect()
+}
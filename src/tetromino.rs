@@ -0,0 +1,56 @@
+use crate::board::Board;
+use crate::piece_set::{Pattern, PieceSet};
+
+// ----
+
+#[derive(Clone)]
+pub struct Tetromino {
+    pub tetromino_type: u8, // index into the game's `PieceSet`
+    pub positions: Vec<usize>,
+    pub rotation_pattern_index: u8,
+    pub pattern_top_left_row: i32,
+    pub pattern_top_left_col: i32,
+}
+
+impl Tetromino {
+    /// A piece at its spawn position on `board`, see `PieceSet::spawn_position`. `None`
+    /// if the board is too small for it, see `PieceSet::min_board_size`.
+    pub fn new(tetromino_type: u8, pieces: &PieceSet, board: &Board) -> Option<Self> {
+        let (pattern_top_left_row, pattern_top_left_col) = pieces.spawn_position(tetromino_type, board.width(), board.hidden_rows());
+        let pattern = pieces.pattern(tetromino_type, 0);
+        let positions = Tetromino::transfer_shape_pattern_to_positions(board, pattern, pattern_top_left_row, pattern_top_left_col)?;
+
+        Some(Self {
+            tetromino_type,
+            positions,
+            pattern_top_left_row,
+            pattern_top_left_col,
+            rotation_pattern_index: 0,
+        })
+    }
+
+    /// A piece on `board` in rotation state `rotation`, its pattern's top left at (`row`, `col`).
+    #[cfg(test)]
+    pub(crate) fn at(tetromino_type: u8, pieces: &PieceSet, board: &Board, rotation: u8, row: i32, col: i32) -> Self {
+        Self {
+            tetromino_type,
+            positions: Self::transfer_shape_pattern_to_positions(board, pieces.pattern(tetromino_type, rotation), row, col).expect("the piece is on the board"),
+            rotation_pattern_index: rotation,
+            pattern_top_left_row: row,
+            pattern_top_left_col: col,
+        }
+    }
+
+    pub fn transfer_shape_pattern_to_positions(board: &Board, pattern: &Pattern, row_offset: i32, col_offset: i32) -> Option<Vec<usize>> {
+        let mut positions = Vec::new();
+        for (row_index, row) in pattern.iter().enumerate() {
+            for (col_index, &is_occupied) in row.iter().enumerate() {
+                if is_occupied {
+                    positions.push(board.get_index(row_index as i32 + row_offset, col_index as i32 + col_offset)?);
+                }
+            }
+        }
+
+        Some(positions)
+    }
+}
@@ -0,0 +1,179 @@
+use crate::board::Board;
+use crate::piece_set::PieceSet;
+use crate::scoring::TSpin;
+use crate::tetromino::Tetromino;
+
+// ----
+
+/// The 3-corner rule: a T which was just rotated into place, with at least three
+/// of the four cells diagonal to its center filled (walls and floor count), is a
+/// T-spin. It's only a mini unless both corners on the side it points towards are
+/// filled, or it got there through its kick table's `full_t_spin_kick`, SRS's TST kick.
+///
+/// Only pieces marked `t_spin` in their piece set count as a T. `kick_index` is
+/// which kick the last rotation used, or `None` when the last thing the piece did
+/// wasn't a rotation.
+pub fn detect_t_spin(board: &Board, pieces: &PieceSet, tetromino: &Tetromino, kick_index: Option<usize>) -> TSpin {
+    let kick_index = match kick_index {
+        Some(kick_index) if pieces.piece(tetromino.tetromino_type).t_spin => kick_index,
+        _ => return TSpin::None,
+    };
+
+    let pattern = pieces.pattern(tetromino.tetromino_type, tetromino.rotation_pattern_index);
+    let size = pattern.len() as i32;
+    let is_block = |row: i32, col: i32| row >= 0 && col >= 0 && row < size && col < size && pattern[row as usize][col as usize];
+
+    // the center is the one block with three neighbours, the T points away from the missing one
+    let mut center = None;
+    for row in 0..size {
+        for col in 0..size {
+            let neighbours = [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().filter(|(r, c)| is_block(row + r, col + c)).count();
+            if is_block(row, col) && neighbours == 3 {
+                center = Some((row, col));
+            }
+        }
+    }
+
+    let (center_row, center_col) = match center {
+        Some(center) => center,
+        None => return TSpin::None,
+    };
+
+    let (facing_row, facing_col) = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+        .iter()
+        .map(|&(r, c)| (-r, -c))
+        .find(|&(r, c)| !is_block(center_row - r, center_col - c))
+        .unwrap();
+
+    let is_filled = |row_offset: i32, col_offset: i32| {
+        let row = tetromino.pattern_top_left_row + center_row + row_offset;
+        let col = tetromino.pattern_top_left_col + center_col + col_offset;
+
+        board.is_blocked(row, col)
+    };
+
+    let corners = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+    let filled_corners = corners.iter().filter(|&&(r, c)| is_filled(r, c)).count();
+    if filled_corners < 3 {
+        return TSpin::None;
+    }
+
+    // the corners sharing a row (or column) with the cell the T points at
+    let filled_front_corners = corners
+        .iter()
+        .filter(|&&(r, c)| (facing_row != 0 && r == facing_row) || (facing_col != 0 && c == facing_col))
+        .filter(|&&(r, c)| is_filled(r, c))
+        .count();
+
+    let full_t_spin_kick = pieces.kick_table(tetromino.tetromino_type).and_then(|table| table.full_t_spin_kick);
+    if filled_front_corners == 2 || full_t_spin_kick == Some(kick_index) {
+        TSpin::Full
+    }
+    else {
+        TSpin::Mini
+    }
+}
+
+// ----
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotation::RotationSystemKind;
+
+    #[test]
+    fn all_four_corners_is_a_full_t_spin() {
+        let pieces = RotationSystemKind::Srs.piece_set();
+        let board = Board::from_picture(10, 20, &[
+            "#.#.......",
+            "...#######",
+            "#.########",
+        ]);
+
+        // pointing down into the slot, center at (38, 1)
+        let t = Tetromino::at(pieces.find("T").unwrap(), &pieces, &board, 2, 37, 0);
+        assert_eq!(detect_t_spin(&board, &pieces, &t, Some(0)), TSpin::Full);
+    }
+
+    #[test]
+    fn only_one_front_corner_is_a_mini() {
+        let pieces = RotationSystemKind::Srs.piece_set();
+        let board = Board::from_picture(10, 20, &[
+            "#.........",
+            "..........",
+            "#.#.......",
+        ]);
+
+        // pointing up, center at (38, 1), with both back corners and one front corner filled
+        let t = Tetromino::at(pieces.find("T").unwrap(), &pieces, &board, 0, 37, 0);
+        assert_eq!(detect_t_spin(&board, &pieces, &t, Some(0)), TSpin::Mini);
+    }
+
+    #[test]
+    fn the_floor_counts_as_corners() {
+        let pieces = RotationSystemKind::Srs.piece_set();
+        let board = Board::from_picture(10, 20, &[
+            "#.........",
+            "..........",
+        ]);
+
+        // pointing up, center on the bottom row
+        let t = Tetromino::at(pieces.find("T").unwrap(), &pieces, &board, 0, 38, 0);
+        assert_eq!(detect_t_spin(&board, &pieces, &t, Some(0)), TSpin::Mini);
+    }
+
+    #[test]
+    fn two_corners_is_no_t_spin() {
+        let pieces = RotationSystemKind::Srs.piece_set();
+        let board = Board::from_picture(10, 20, &[
+            "..........",
+            "..........",
+            "#.#.......",
+        ]);
+
+        let t = Tetromino::at(pieces.find("T").unwrap(), &pieces, &board, 0, 37, 0);
+        assert_eq!(detect_t_spin(&board, &pieces, &t, Some(0)), TSpin::None);
+    }
+
+    #[test]
+    fn only_counts_after_a_rotation() {
+        let pieces = RotationSystemKind::Srs.piece_set();
+        let board = Board::from_picture(10, 20, &[
+            "#.#.......",
+            "...#######",
+            "#.########",
+        ]);
+
+        let t = Tetromino::at(pieces.find("T").unwrap(), &pieces, &board, 2, 37, 0);
+        assert_eq!(detect_t_spin(&board, &pieces, &t, None), TSpin::None);
+    }
+
+    #[test]
+    fn the_tst_kick_makes_a_mini_full() {
+        let pieces = RotationSystemKind::Srs.piece_set();
+        let board = Board::from_picture(10, 20, &[
+            "#.........",
+            "..........",
+            "#.#.......",
+        ]);
+
+        let t = Tetromino::at(pieces.find("T").unwrap(), &pieces, &board, 0, 37, 0);
+        assert_eq!(detect_t_spin(&board, &pieces, &t, Some(4)), TSpin::Full);
+        assert_eq!(detect_t_spin(&board, &pieces, &t, Some(3)), TSpin::Mini);
+    }
+
+    #[test]
+    fn only_kick_tables_with_a_full_t_spin_kick_upgrade_minis() {
+        // the same spot as above, but ARS has no TST kick
+        let pieces = RotationSystemKind::Ars.piece_set();
+        let board = Board::from_picture(10, 20, &[
+            "#.........",
+            "..........",
+            "#.#.......",
+        ]);
+
+        // ARS's T points up in rotation state 2, and sits a row lower in its box
+        let t = Tetromino::at(pieces.find("T").unwrap(), &pieces, &board, 2, 36, 0);
+        assert_eq!(detect_t_spin(&board, &pieces, &t, Some(4)), TSpin::Mini);
+    }
+}